@@ -0,0 +1,235 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        DepRole,
+        browsable_url,
+        maybe_read,
+    },
+    git::remote_url,
+};
+
+fn load_yaml(log: &Logger, path: &Path) -> Option<serde_yaml::Value> {
+    let text = match maybe_read(path) {
+        Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+        Ok(None) => return None,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading CI file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            return None;
+        },
+    };
+    match serde_yaml::from_str(&text) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!(
+                log,
+                "Error parsing CI file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn yaml_files(dir: &Path) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> =
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "yml" || e == "yaml").unwrap_or(false))
+            .collect();
+    out.sort();
+    out
+}
+
+fn github_action_url(uses: &str) -> Option<String> {
+    if uses.starts_with("./") || uses.starts_with("docker://") {
+        return None;
+    }
+    let (action, _ref) = uses.split_once('@')?;
+    let mut parts = action.split('/');
+    Some(format!("https://github.com/{}/{}", parts.next()?, parts.next()?))
+}
+
+fn steps_uses(v: &serde_yaml::Value) -> Vec<&str> {
+    v
+        .get("steps")
+        .and_then(|s| s.as_sequence())
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.get("uses").and_then(|u| u.as_str()))
+        .collect()
+}
+
+fn github_actions_uses(doc: &serde_yaml::Value) -> Vec<&str> {
+    let mut out = vec![];
+    for job in doc.get("jobs").and_then(|j| j.as_mapping()).into_iter().flat_map(|j| j.values()) {
+        out.extend(job.get("uses").and_then(|u| u.as_str()));
+        out.extend(steps_uses(job));
+    }
+    if let Some(runs) = doc.get("runs") {
+        out.extend(steps_uses(runs));
+    }
+    out
+}
+
+fn instance_host(log: &Logger, path: &Path) -> Option<String> {
+    let url = url::Url::parse(&browsable_url(&remote_url(log, path)?)?).ok()?;
+    Some(url.host_str()?.to_string())
+}
+
+fn gitlab_component_url(component: &str, instance: Option<&str>) -> Option<String> {
+    let (path, _version) = component.split_once('@')?;
+    let (host, path) = path.split_once('/')?;
+    let host = if host.starts_with('$') {
+        instance?
+    } else {
+        host
+    };
+    let (project, _name) = path.rsplit_once('/')?;
+    Some(format!("https://{}/{}", host, project))
+}
+
+fn gitlab_include_urls(log: &Logger, doc: &serde_yaml::Value, instance: Option<&str>) -> Vec<String> {
+    let includes = match doc.get("include") {
+        Some(serde_yaml::Value::Sequence(s)) => s.iter().collect(),
+        Some(i) => vec![i],
+        None => vec![],
+    };
+    let mut out = vec![];
+    for include in includes {
+        // Plain strings are local or remote files
+        let url = if let Some(component) = include.get("component").and_then(|c| c.as_str()) {
+            gitlab_component_url(component, instance)
+        } else if let Some(project) = include.get("project").and_then(|p| p.as_str()) {
+            instance.map(|host| format!("https://{}/{}", host, project.trim_matches('/')))
+        } else {
+            continue;
+        };
+        match url {
+            Some(u) => out.push(u),
+            None => warn!(
+                log,
+                "Unable to determine GitLab project for include, no remote to take the instance host from";
+                "include" => #? include
+            ),
+        }
+    }
+    out
+}
+
+pub fn process_ci_tooling(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    // Project urls, as full paths so nested GitLab groups are kept
+    let mut urls = BTreeSet::new();
+
+    // GitHub Actions workflows, plus composite actions defined in the repo or at the root
+    let mut action_files = yaml_files(&path.join(".github/workflows"));
+    for dir in fs::read_dir(path.join(".github/actions")).into_iter().flatten().flatten() {
+        action_files.extend(yaml_files(&dir.path()).into_iter().filter(|p| p.file_stem().unwrap() == "action"));
+    }
+    action_files.extend(yaml_files(path).into_iter().filter(|p| p.file_stem().unwrap() == "action"));
+    for file in action_files {
+        if let Some(doc) = load_yaml(log, &file) {
+            urls.extend(github_actions_uses(&doc).into_iter().filter_map(github_action_url));
+        }
+    }
+
+    // GitLab CI
+    let gitlab_ci_path = path.join(".gitlab-ci.yml");
+    if let Some(doc) = load_yaml(log, &gitlab_ci_path) {
+        let log = log.new(o!("file" => gitlab_ci_path.to_string_lossy().to_string()));
+        urls.extend(gitlab_include_urls(&log, &doc, instance_host(&log, path).as_deref()));
+    }
+
+    // pre-commit, where `local` and `meta` hooks aren't repos
+    if let Some(doc) = load_yaml(log, &path.join(".pre-commit-config.yaml")) {
+        for repo in doc.get("repos").and_then(|r| r.as_sequence()).into_iter().flatten() {
+            match repo.get("repo").and_then(|r| r.as_str()) {
+                Some("local" | "meta") | None => { },
+                Some(r) => match browsable_url(r) {
+                    Some(u) => {
+                        urls.insert(u);
+                    },
+                    None => warn!(log, "Pre-commit repo isn't a remote url"; "repo" => r),
+                },
+            }
+        }
+    }
+    let ctx = ctx.with_role(DepRole::Tooling);
+    for url in urls {
+        let ctx = ctx.clone();
+        pool.push(spawn(async move {
+            ctx.add_url(&url).await;
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_action_urls() {
+        assert_eq!(github_action_url("actions/checkout@v4").as_deref(), Some("https://github.com/actions/checkout"));
+        assert_eq!(
+            github_action_url("github/codeql-action/init@v3").as_deref(),
+            Some("https://github.com/github/codeql-action"),
+        );
+        assert_eq!(github_action_url("./.github/actions/setup"), None);
+        assert_eq!(github_action_url("docker://alpine:3.18"), None);
+    }
+
+    #[test]
+    fn gitlab_components() {
+        assert_eq!(
+            gitlab_component_url("gitlab.com/group/sub/project/lint@1.0", None).as_deref(),
+            Some("https://gitlab.com/group/sub/project"),
+        );
+        assert_eq!(
+            gitlab_component_url("$CI_SERVER_FQDN/group/project/build@main", Some("git.example.com")).as_deref(),
+            Some("https://git.example.com/group/project"),
+        );
+        assert_eq!(gitlab_component_url("$CI_SERVER_FQDN/group/project/build@main", None), None);
+    }
+
+    #[test]
+    fn gitlab_includes() {
+        let log = Logger::root(slog::Discard, o!());
+        let doc: serde_yaml::Value = serde_yaml::from_str("\
+include:
+  - local: ci/build.yml
+  - project: group/sub/templates
+    file: a.yml
+  - component: gitlab.com/org/comp/x@1
+").unwrap();
+        assert_eq!(gitlab_include_urls(&log, &doc, Some("git.example.com")), vec![
+            "https://git.example.com/group/sub/templates",
+            "https://gitlab.com/org/comp",
+        ]);
+        assert_eq!(gitlab_include_urls(&log, &doc, None), vec!["https://gitlab.com/org/comp"]);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeSet,
+    path::Path,
+};
+use anyhow::{
+    anyhow,
+    Result,
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::task::JoinHandle;
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+        process_git_dep,
+    },
+    java,
+};
+
+const CLOJARS: &str = "https://repo.clojars.org";
+
+// Maps and sets are kept as flat lists
+enum Form {
+    List(Vec<Form>),
+    Vector(Vec<Form>),
+    Map(Vec<Form>),
+    Str(String),
+    Atom(String),
+}
+
+impl Form {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Form::Atom(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    fn str(&self) -> Option<&str> {
+        match self {
+            Form::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> &[Form] {
+        match self {
+            Form::List(c) | Form::Vector(c) | Form::Map(c) => c,
+            _ => &[],
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Form> {
+        match self {
+            Form::Map(c) => c.chunks(2).find(|kv| kv[0].atom() == Some(key)).and_then(|kv| kv.get(1)),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Self {
+        Reader { chars: text.chars().peekable() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            match c {
+                ';' => {
+                    for c in self.chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                },
+                c if c.is_whitespace() || *c == ',' => {
+                    self.chars.next();
+                },
+                _ => break,
+            }
+        }
+    }
+
+    fn read_until(&mut self, end: char) -> Result<Vec<Form>> {
+        let mut out = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() == Some(&end) {
+                self.chars.next();
+                return Ok(out);
+            }
+            out.extend(self.read()?);
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<Form>> {
+        self.skip_whitespace();
+        let c = self.chars.next().ok_or_else(|| anyhow!("Unexpected end of input"))?;
+        Ok(Some(match c {
+            '(' => Form::List(self.read_until(')')?),
+            '[' => Form::Vector(self.read_until(']')?),
+            '{' => Form::Map(self.read_until('}')?),
+            ')' | ']' | '}' => return Err(anyhow!("Unbalanced {}", c)),
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = self.chars.next() {
+                    match c {
+                        '\\' => s.extend(self.chars.next()),
+                        '"' => break,
+                        c => s.push(c),
+                    }
+                }
+                Form::Str(s)
+            },
+            '#' => match self.chars.peek() {
+                Some('_') => {
+                    // The discarded form may itself be a discard or metadata, like `#_#_ k v`
+                    self.chars.next();
+                    while self.read()?.is_none() { }
+                    return Ok(None);
+                },
+                Some('{') => {
+                    self.chars.next();
+                    Form::List(self.read_until('}')?)
+                },
+
+                // Tagged literals, regexes, var quotes, etc. - read the tagged form
+                _ => return self.read(),
+            },
+            '\'' | '`' | '~' | '@' => return self.read(),
+            '^' => {
+                self.read()?;
+                return Ok(None);
+            },
+            c => {
+                let mut s = c.to_string();
+                while let Some(c) = self.chars.peek() {
+                    if c.is_whitespace() || "()[]{}\",;".contains(*c) {
+                        break;
+                    }
+                    s.push(*c);
+                    self.chars.next();
+                }
+                Form::Atom(s)
+            },
+        }))
+    }
+
+    fn read_all(mut self) -> Result<Vec<Form>> {
+        let mut out = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek().is_none() {
+                return Ok(out);
+            }
+            out.extend(self.read()?);
+        }
+    }
+}
+
+fn load_forms(log: &Logger, path: &Path) -> Option<Vec<Form>> {
+    let bytes = match maybe_read(path) {
+        Ok(None) => return None,
+        Ok(Some(b)) => b,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading dep file";
+                "err" => #? e
+            );
+            return None;
+        },
+    };
+    match Reader::new(&String::from_utf8_lossy(&bytes)).read_all() {
+        Ok(f) => Some(f),
+        Err(e) => {
+            warn!(
+                log,
+                "Error parsing dep file";
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn split_lib(lib: &str) -> (String, String) {
+    match lib.split_once('/') {
+        Some((g, n)) => (g.to_string(), n.to_string()),
+        None => (lib.to_string(), lib.to_string()),
+    }
+}
+
+fn infer_git_url(lib: &str) -> Option<String> {
+    let (group, name) = lib.split_once('/')?;
+    for (prefix, host) in [
+        ("io.github.", "github.com"),
+        ("com.github.", "github.com"),
+        ("io.gitlab.", "gitlab.com"),
+        ("com.gitlab.", "gitlab.com"),
+        ("io.bitbucket.", "bitbucket.org"),
+        ("org.bitbucket.", "bitbucket.org"),
+    ] {
+        if let Some(owner) = group.strip_prefix(prefix) {
+            return Some(format!("https://{}/{}/{}", host, owner, name));
+        }
+    }
+    if let Some(owner) = group.strip_prefix("ht.sr.") {
+        return Some(format!("https://git.sr.ht/~{}/{}", owner, name));
+    }
+    None
+}
+
+fn lein_deps(form: &Form, deps: &mut BTreeSet<(String, String, String)>) {
+    let children = form.children();
+    for (i, child) in children.iter().enumerate() {
+        let key = child.atom().unwrap_or_default();
+        if [":dependencies", ":managed-dependencies", ":plugins"].contains(&key) {
+            if let Some(Form::Vector(entries)) = children.get(i + 1) {
+                for entry in entries {
+                    let entry = entry.children();
+                    if let (Some(lib), Some(version)) =
+                        (entry.first().and_then(|f| f.atom()), entry.get(1).and_then(|f| f.str())) {
+                        let (group, name) = split_lib(lib);
+                        deps.insert((group, name, version.to_string()));
+                    }
+                }
+            }
+        }
+        lein_deps(child, deps);
+    }
+}
+
+pub fn process_clojure_lein(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let project_path = path.join("project.clj");
+    let log = log.new(o!("file" => project_path.to_string_lossy().to_string()));
+    let forms = match load_forms(&log, &project_path) {
+        Some(f) => f,
+        None => return,
+    };
+    let mut deps = BTreeSet::new();
+    for form in &forms {
+        if form.children().first().and_then(|f| f.atom()) == Some("defproject") {
+            lein_deps(form, &mut deps);
+        }
+    }
+    for (group, name, version) in deps {
+        java::process_dep(&log, ctx, pool, &[CLOJARS], group, name, version);
+    }
+}
+
+fn tools_deps<'a>(form: &'a Form, deps: &mut Vec<(&'a str, &'a Form)>) {
+    let children = form.children();
+    for (i, child) in children.iter().enumerate() {
+        let key = child.atom().unwrap_or_default();
+        if [":deps", ":extra-deps", ":replace-deps", ":override-deps"].contains(&key) {
+            if let Some(Form::Map(entries)) = children.get(i + 1) {
+                for kv in entries.chunks(2) {
+                    if let (Some(lib), Some(coord)) = (kv[0].atom(), kv.get(1)) {
+                        deps.push((lib, coord));
+                    }
+                }
+            }
+        }
+        tools_deps(child, deps);
+    }
+}
+
+pub fn process_clojure_deps(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let deps_path = path.join("deps.edn");
+    let log = log.new(o!("file" => deps_path.to_string_lossy().to_string()));
+    let forms = match load_forms(&log, &deps_path) {
+        Some(f) => f,
+        None => return,
+    };
+    let mut deps = vec![];
+    for form in &forms {
+        tools_deps(form, &mut deps);
+    }
+    let mut mvn_deps = BTreeSet::new();
+    let mut git_deps = BTreeSet::new();
+    for (lib, coord) in deps {
+        if let Some(version) = coord.get(":mvn/version").and_then(|v| v.str()) {
+            let (group, name) = split_lib(lib);
+            mvn_deps.insert((group, name, version.to_string()));
+        } else if let Some(url) = coord.get(":git/url").and_then(|v| v.str()) {
+            git_deps.insert(url.to_string());
+        } else if coord.get(":git/sha").is_some() || coord.get(":git/tag").is_some() || coord.get(":sha").is_some() {
+            match infer_git_url(lib) {
+                Some(url) => {
+                    git_deps.insert(url);
+                },
+                None => warn!(log, "Unable to infer git url for git dependency"; "dep" => lib),
+            }
+        }
+    }
+    for url in git_deps {
+        process_git_dep(&log, ctx, pool, url);
+    }
+    for (group, name, version) in mvn_deps {
+        java::process_dep(&log, ctx, pool, &[CLOJARS], group, name, version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_deps_edn() {
+        let forms =
+            Reader::new(
+                r#"
+; comment
+{:deps {org.clojure/clojure {:mvn/version "1.11.1"}
+        #_#_ignored/lib {:mvn/version "0"}
+        io.github.a/b ^:meta {:git/sha "abc", :tag "v\"1\""}}
+ :aliases {:re #"x[)]"}}
+"#,
+            )
+                .read_all()
+                .unwrap();
+        assert_eq!(forms.len(), 1);
+        let deps = forms[0].get(":deps").unwrap();
+        let libs: Vec<&str> = deps.children().chunks(2).filter_map(|kv| kv[0].atom()).collect();
+        assert_eq!(libs, vec!["org.clojure/clojure", "io.github.a/b"]);
+        let clojure = deps.get("org.clojure/clojure").unwrap();
+        assert_eq!(clojure.get(":mvn/version").and_then(|v| v.str()), Some("1.11.1"));
+        assert_eq!(deps.get("io.github.a/b").unwrap().get(":tag").and_then(|v| v.str()), Some("v\"1\""));
+    }
+
+    #[test]
+    fn reader_unbalanced() {
+        assert!(Reader::new("(defproject x \"1\"]").read_all().is_err());
+        assert!(Reader::new("(defproject x").read_all().is_err());
+    }
+
+    #[test]
+    fn infer_git_urls() {
+        assert_eq!(infer_git_url("io.github.a/b").as_deref(), Some("https://github.com/a/b"));
+        assert_eq!(infer_git_url("ht.sr.a/b").as_deref(), Some("https://git.sr.ht/~a/b"));
+        assert_eq!(infer_git_url("org.clojure/clojure"), None);
+    }
+}
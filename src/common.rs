@@ -1,4 +1,5 @@
 use std::{
+    env,
     fs,
     io::ErrorKind,
     path::{
@@ -11,7 +12,10 @@ use std::{
     },
     time::Duration,
     num::NonZeroU32,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        BTreeSet,
+    },
 };
 use bread_common::{
     AccountId,
@@ -48,9 +52,14 @@ use serde::{
 };
 use slog::{
     Logger,
+    o,
     warn,
     debug,
 };
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
 use crate::{
     aes,
 };
@@ -64,26 +73,56 @@ pub struct WorkingAccount {
     pub weight: Option<u32>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DepRole {
+    Runtime,
+    Dev,
+    Peer,
+    Optional,
+    // CI actions, hooks and other build tooling
+    Tooling,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct WorkingProvenance {
+    #[serde(default)]
+    pub funding: BTreeSet<String>,
+    #[serde(default)]
+    pub roles: BTreeSet<DepRole>,
+}
+
+impl WorkingProvenance {
+    pub fn merge(&mut self, other: WorkingProvenance) {
+        self.funding.extend(other.funding);
+        self.roles.extend(other.roles);
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct WorkingWeights {
     pub accounts: HashMap<AccountId, WorkingAccount>,
     pub projects: HashMap<String, Option<u32>>,
+    #[serde(default)]
+    pub provenance: HashMap<String, WorkingProvenance>,
 }
 
 #[derive(Clone)]
 pub struct Supercontext {
     cache_path: PathBuf,
     hc: Client,
+    pub include_indirect: bool,
     pub limiters: Arc<
         Mutex<HashMap<String, Arc<RateLimiter<NotKeyed, InMemoryState, QuantaClock, NoOpMiddleware>>>>,
     >,
 }
 
 impl Supercontext {
-    pub fn new(cache_path: PathBuf) -> Self {
+    pub fn new(cache_path: PathBuf, include_indirect: bool) -> Self {
         Supercontext {
             cache_path: cache_path,
             hc: reqwest::Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            include_indirect,
             limiters: Arc::new(Mutex::new(HashMap::new())),
         }
     }
@@ -109,6 +148,7 @@ impl<T> LogErr<T> for std::result::Result<T, anyhow::Error> {
 pub struct Context {
     pub supercontext: Supercontext,
     pub config: Arc<Mutex<WorkingWeights>>,
+    pub role: Option<DepRole>,
 }
 
 impl Context {
@@ -116,6 +156,14 @@ impl Context {
         Context {
             supercontext: supercontext,
             config: Arc::new(Mutex::new(WorkingWeights::default())),
+            role: None,
+        }
+    }
+
+    pub fn with_role(&self, role: DepRole) -> Self {
+        Context {
+            role: Some(role),
+            ..self.clone()
         }
     }
 
@@ -169,10 +217,37 @@ impl Context {
     }
 
     pub async fn add_url(&self, raw_url: &str) {
-        self.config.lock().unwrap().projects.insert(raw_url.to_string(), None);
+        let mut config = self.config.lock().unwrap();
+        config.projects.insert(raw_url.to_string(), None);
+        if let Some(role) = self.role {
+            config.provenance.entry(raw_url.to_string()).or_default().roles.insert(role);
+        }
+    }
+
+    pub async fn add_funding(&self, project_url: &str, funding_url: &str) {
+        self
+            .config
+            .lock()
+            .unwrap()
+            .provenance
+            .entry(project_url.to_string())
+            .or_default()
+            .funding
+            .insert(funding_url.to_string());
     }
 
     pub async fn maybe_add_url(&self, log: &Logger, url: &str) -> bool {
+        self.maybe_add_funded_url(log, url, &[]).await
+    }
+
+    async fn add_funded_url(&self, url: &str, funding: &[String]) {
+        self.add_url(url).await;
+        for f in funding {
+            self.add_funding(url, f).await;
+        }
+    }
+
+    pub async fn maybe_add_funded_url(&self, log: &Logger, url: &str, funding: &[String]) -> bool {
         match aes!({
             if url.is_empty() {
                 return Ok(false);
@@ -181,10 +256,10 @@ impl Context {
             let host = url.host_str().ok_or_else(|| anyhow!("URL missing host"))?;
             if host.ends_with(".github.io") {
                 let org = host.split(".").next().unwrap();
-                self.add_url(&format!("https://github.com/{}{}", org, url.path())).await;
+                self.add_funded_url(&format!("https://github.com/{}{}", org, url.path()), funding).await;
                 return Ok(true);
             }
-            if ["github.com", "gitlab.com", "sr.ht"].into_iter().any(|d| host.ends_with(d)) ||
+            if ["github.com", "gitlab.com", "bitbucket.org", "sr.ht"].into_iter().any(|d| host.ends_with(d)) ||
                 host.split(".").any(|s| s == "gitlab") {
                 let mut path: Vec<String> = url.path().split('/').map(|s| s.to_string()).collect();
                 let mut matched = false;
@@ -196,12 +271,17 @@ impl Context {
                     path.truncate(3);
                     matched = true;
                 }
-                if host == "sr.ht" {
+                if host == "bitbucket.org" {
+                    path.truncate(3);
+                    matched = true;
+                }
+                if host == "sr.ht" || host == "git.sr.ht" {
                     path.truncate(3);
                     matched = true;
                 }
                 if matched {
-                    self.add_url(&format!("https://{}{}", host, path.join("/"))).await;
+                    let url = format!("https://{}{}", host, path.join("/").trim_end_matches(".git"));
+                    self.add_funded_url(&url, funding).await;
                 }
                 return Ok(true);
             }
@@ -219,6 +299,101 @@ impl Context {
             },
         }
     }
+
+    pub async fn add_repo_url(&self, log: &Logger, url: &str) -> bool {
+        let url = match browsable_url(url) {
+            Some(u) => u,
+            None => return false,
+        };
+        if is_normalized_forge_url(&url) {
+            self.maybe_add_url(log, &url).await;
+        } else {
+            // GitLab projects may be in nested groups, so aren't cut down to two path segments
+            self.add_url(url.split("/-/").next().unwrap()).await;
+        }
+        true
+    }
+}
+
+fn is_normalized_forge_url(url: &str) -> bool {
+    let url = match Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return false,
+    };
+    let host = url.host_str().unwrap_or_default();
+    ["github.com", "bitbucket.org", "sr.ht", "git.sr.ht"].contains(&host) || host.ends_with(".github.io")
+}
+
+pub fn browsable_url(url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Some(url.to_string());
+    }
+    if let Some(r) = url.strip_prefix("git://") {
+        return Some(format!("https://{}", r));
+    }
+    if let Some(r) = url.strip_prefix("ssh://") {
+        let (host, path) = r.rsplit('@').next().unwrap().split_once('/')?;
+        return Some(format!("https://{}/{}", host.split(':').next().unwrap(), path));
+    }
+
+    // scp-style `user@host:path`; anything else is a local path
+    let (host, path) = url.split_once(':')?;
+    if host.is_empty() || host.contains('/') {
+        return None;
+    }
+    Some(format!("https://{}/{}", host.rsplit('@').next().unwrap(), path.trim_start_matches('/')))
+}
+
+pub fn process_git_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, url: String) {
+    let log = log.new(o!("dep" => url.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        if !ctx.add_repo_url(&log, &url).await {
+            warn!(log, "Dependency git url isn't a remote url, skipping");
+        }
+    }));
+}
+
+pub fn is_gitlab_url(url: &str) -> bool {
+    Url::parse(url).map(|u| u.host_str().unwrap_or_default().split('.').any(|l| l == "gitlab")).unwrap_or(false)
+}
+
+fn is_git_url(url: &str) -> bool {
+    url.ends_with(".git") || url.starts_with("git@") || url.starts_with("ssh://") || url.starts_with("git://")
+}
+
+pub fn process_dep_candidates(
+    log: &Logger,
+    ctx: &Context,
+    pool: &mut Vec<JoinHandle<()>>,
+    name: &str,
+    candidates: Vec<String>,
+) {
+    let log = log.new(o!("dep" => name.to_string()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        for c in &candidates {
+            let url = browsable_url(c).unwrap_or_else(|| c.clone());
+            if is_gitlab_url(&url) {
+                ctx.add_repo_url(&log, &url).await;
+                return;
+            }
+            if ctx.maybe_add_url(&log, &url).await {
+                return;
+            }
+        }
+        if let Some(c) = candidates.first().filter(|c| is_git_url(c)) {
+            if ctx.add_repo_url(&log, c).await {
+                return;
+            }
+        }
+        warn!(log, "No repo-ish url found in dep metadata");
+    }));
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from)
 }
 
 pub fn maybe_read(p: &Path) -> Result<Option<Vec<u8>>> {
@@ -234,3 +409,101 @@ pub fn maybe_read(p: &Path) -> Result<Option<Vec<u8>>> {
         Ok(r) => return Ok(Some(r)),
     }
 }
+
+pub fn xml_nodes<'d>(
+    factory: &sxd_xpath::Factory,
+    xctx: &sxd_xpath::Context<'d>,
+    node: sxd_xpath::nodeset::Node<'d>,
+    xpath: &str,
+) -> Vec<sxd_xpath::nodeset::Node<'d>> {
+    match factory.build(xpath).unwrap().unwrap().evaluate(xctx, node).unwrap() {
+        sxd_xpath::Value::Nodeset(n) => n.document_order(),
+        _ => vec![],
+    }
+}
+
+pub fn xml_text<'d>(
+    factory: &sxd_xpath::Factory,
+    xctx: &sxd_xpath::Context<'d>,
+    node: sxd_xpath::nodeset::Node<'d>,
+    xpath: &str,
+) -> String {
+    factory.build(&format!("normalize-space({})", xpath)).unwrap().unwrap().evaluate(xctx, node).unwrap().string()
+}
+
+type VersionParts = Vec<(u64, String)>;
+
+pub fn version_key(version: &str) -> (VersionParts, bool, VersionParts) {
+    let parts = |s: &str| {
+        s
+            .split(['.', '_'])
+            .map(|part| {
+                let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (part[.. digits].parse().unwrap_or(0), part[digits ..].to_string())
+            })
+            .collect::<VersionParts>()
+    };
+    let version = version.split('+').next().unwrap();
+
+    // Prereleases sort before their release
+    match version.split_once('-') {
+        Some((release, prerelease)) => (parts(release), false, parts(prerelease)),
+        None => (parts(version), true, vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browsable_urls() {
+        assert_eq!(browsable_url("https://github.com/a/b.git").as_deref(), Some("https://github.com/a/b"));
+        assert_eq!(browsable_url("git@github.com:a/b.git").as_deref(), Some("https://github.com/a/b"));
+        assert_eq!(browsable_url("ssh://git@example.com:2222/a/b.git").as_deref(), Some("https://example.com/a/b"));
+        assert_eq!(browsable_url("git://example.com/a/b").as_deref(), Some("https://example.com/a/b"));
+        assert_eq!(browsable_url("../local/path"), None);
+        assert_eq!(browsable_url("/srv/git/repo.git"), None);
+    }
+
+    #[test]
+    fn version_keys() {
+        assert!(version_key("1.10.0") > version_key("1.9.2"));
+        assert!(version_key("2.0") > version_key("1.99"));
+        assert!(version_key("1.0.0-rc2") > version_key("1.0.0-rc1"));
+        assert!(version_key("1.0.0") > version_key("1.0.0-rc1"));
+        assert!(version_key("1.0.1-beta") > version_key("1.0.0"));
+        assert_eq!(version_key("1.0.0+build.5"), version_key("1.0.0"));
+    }
+
+    #[test]
+    fn normalized_forge_urls() {
+        assert!(is_normalized_forge_url("https://github.com/a/b/tree/main"));
+        assert!(is_normalized_forge_url("https://git.sr.ht/~a/b"));
+        assert!(!is_normalized_forge_url("https://gitlab.com/group/sub/project"));
+        assert!(!is_normalized_forge_url("https://gitlab.freedesktop.org/mesa/mesa"));
+        assert!(!is_normalized_forge_url("https://git.example.com/a/b"));
+    }
+
+    #[tokio::test]
+    async fn add_repo_urls() {
+        let log = Logger::root(slog::Discard, o!());
+        let ctx = Context::new(Supercontext::new(env::temp_dir(), false));
+        for url in [
+            "git@github.com:a/b.git",
+            "https://gitlab.com/group/sub/project.git",
+            "https://gitlab.freedesktop.org/mesa/mesa/-/tree/main",
+            "ssh://git@git.example.com/c/d.git",
+        ] {
+            assert!(ctx.add_repo_url(&log, url).await);
+        }
+        assert!(!ctx.add_repo_url(&log, "../local").await);
+        let projects: BTreeSet<String> = ctx.config.lock().unwrap().projects.keys().cloned().collect();
+        assert_eq!(projects, BTreeSet::from([
+            "https://github.com/a/b".to_string(),
+            "https://gitlab.com/group/sub/project".to_string(),
+            "https://gitlab.freedesktop.org/mesa/mesa".to_string(),
+            "https://git.example.com/c/d".to_string(),
+        ]));
+    }
+}
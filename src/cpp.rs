@@ -0,0 +1,482 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use structre::structre;
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        DepRole,
+        home_dir,
+        maybe_read,
+        process_dep_candidates,
+        version_key,
+    },
+    aes,
+};
+
+const CONAN_CENTER_INDEX: &str = "https://github.com/conan-io/conan-center-index";
+
+#[structre(r#"^\s*(?P<name>name|url|homepage)\s*=\s*["'](?P<value>[^"']+)["']"#)]
+struct RecipeAttr {
+    name: String,
+    value: String,
+}
+
+fn read_text(log: &Logger, path: &Path) -> Option<String> {
+    match maybe_read(path) {
+        Ok(b) => b.map(|b| String::from_utf8_lossy(&b).to_string()),
+        Err(e) => {
+            warn!(
+                log,
+                "Error reading file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn cmake_calls(text: &str, function: &str) -> Vec<Vec<String>> {
+    let mut out = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(&format!("{}(", function)) {
+        rest = &rest[start + function.len() + 1 ..];
+
+        // Arguments may contain nested parentheses, and quoted or commented out ones
+        let mut depth = 0;
+        let mut quoted = false;
+        let mut comment = false;
+        let end =
+            rest
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '\n' => comment = false,
+                        _ if comment => { },
+                        '"' => quoted = !quoted,
+                        _ if quoted => { },
+                        '#' => comment = true,
+                        '(' => depth += 1,
+                        ')' if depth == 0 => return true,
+                        ')' => depth -= 1,
+                        _ => { },
+                    }
+                    false
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+        out.push(
+            rest[.. end]
+                .lines()
+                .map(|l| l.split('#').next().unwrap())
+                .flat_map(|l| l.split_whitespace())
+                .map(|a| a.trim_matches('"').to_string())
+                .collect(),
+        );
+        rest = &rest[end ..];
+    }
+    out
+}
+
+fn cmake_arg<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|a| a.as_str())
+}
+
+fn vcpkg_roots(path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![];
+    for var in ["VCPKG_ROOT", "VCPKG_INSTALLATION_ROOT"] {
+        if let Some(r) = env::var_os(var) {
+            out.push(PathBuf::from(r));
+        }
+    }
+
+    // Commonly a submodule of the project
+    out.push(path.join("vcpkg"));
+    if let Some(h) = home_dir() {
+        out.push(h.join("vcpkg"));
+    }
+    out.into_iter().filter(|r| r.join("ports").is_dir()).collect()
+}
+
+fn vcpkg_port_urls(log: &Logger, roots: &[PathBuf], name: &str) -> Option<Vec<String>> {
+    #[derive(Deserialize)]
+    struct PortManifest {
+        homepage: Option<String>,
+    }
+
+    let port_dir = roots.iter().map(|r| r.join("ports").join(name)).find(|d| d.is_dir())?;
+    let mut out = vec![];
+    if let Some(portfile) = read_text(log, &port_dir.join("portfile.cmake")) {
+        for args in cmake_calls(&portfile, "vcpkg_from_github") {
+            if let Some(repo) = cmake_arg(&args, "REPO") {
+                let host = cmake_arg(&args, "GITHUB_HOST").unwrap_or("https://github.com");
+                out.push(format!("{}/{}", host, repo));
+            }
+        }
+        for args in cmake_calls(&portfile, "vcpkg_from_gitlab") {
+            if let (Some(host), Some(repo)) = (cmake_arg(&args, "GITLAB_URL"), cmake_arg(&args, "REPO")) {
+                out.push(format!("{}/{}", host.trim_end_matches('/'), repo));
+            }
+        }
+        for args in cmake_calls(&portfile, "vcpkg_from_bitbucket") {
+            if let Some(repo) = cmake_arg(&args, "REPO") {
+                out.push(format!("https://bitbucket.org/{}", repo));
+            }
+        }
+        for args in cmake_calls(&portfile, "vcpkg_from_git") {
+            if let Some(url) = cmake_arg(&args, "URL") {
+                out.push(url.to_string());
+            }
+        }
+    }
+    if let Some(manifest) = read_text(log, &port_dir.join("vcpkg.json")) {
+        match serde_json::from_str::<PortManifest>(&manifest) {
+            Ok(m) => out.extend(m.homepage),
+            Err(e) => warn!(log, "Error parsing port manifest"; "port" => name, "err" => #? e),
+        }
+    }
+    Some(out)
+}
+
+fn vcpkg_dep_names(deps: Option<&serde_json::Value>) -> Vec<String> {
+    deps
+        .and_then(|d| d.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.as_str().or_else(|| d.get("name").and_then(|n| n.as_str())))
+        .map(|n| n.to_string())
+        .collect()
+}
+
+pub fn process_cpp_vcpkg(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let manifest_path = path.join("vcpkg.json");
+    let log = log.new(o!("file" => manifest_path.to_string_lossy().to_string()));
+    let manifest: serde_json::Value = match maybe_read(&manifest_path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(m)) => m,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading vcpkg.json";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let mut deps = BTreeMap::new();
+    for name in vcpkg_dep_names(manifest.get("dependencies")) {
+        deps.insert(name, DepRole::Runtime);
+    }
+    for feature in manifest.get("features").and_then(|f| f.as_object()).into_iter().flat_map(|f| f.values()) {
+        for name in vcpkg_dep_names(feature.get("dependencies")) {
+            deps.entry(name).or_insert(DepRole::Optional);
+        }
+    }
+    let roots = vcpkg_roots(path);
+    if roots.is_empty() && !deps.is_empty() {
+        warn!(log, "No vcpkg checkout found, set VCPKG_ROOT to resolve ports");
+        return;
+    }
+    for (name, role) in deps {
+        // Helper ports like `vcpkg-cmake` are build scripts, not upstream projects
+        if name.starts_with("vcpkg-") {
+            continue;
+        }
+        match vcpkg_port_urls(&log, &roots, &name) {
+            Some(candidates) => process_dep_candidates(&log, &ctx.with_role(role), pool, &name, candidates),
+            None => warn!(log, "Port not found in vcpkg checkout"; "dep" => name),
+        }
+    }
+}
+
+// Like `zlib/1.2.13@user/channel#revision`
+struct ConanRef {
+    name: String,
+    version: String,
+}
+
+fn parse_conan_ref(r: &str) -> Option<ConanRef> {
+    let r = r.split(['#', '@']).next().unwrap().trim();
+    let (name, version) = r.split_once('/')?;
+    Some(ConanRef {
+        name: name.to_string(),
+        version: version.to_string(),
+    })
+}
+
+fn conanfile_txt_deps(text: &str, deps: &mut BTreeMap<String, (DepRole, String)>) {
+    let mut role = None;
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            role = match section {
+                "requires" => Some(DepRole::Runtime),
+                "tool_requires" | "build_requires" | "test_requires" => Some(DepRole::Dev),
+                _ => None,
+            };
+            continue;
+        }
+        let (role, r) = match (role, parse_conan_ref(line)) {
+            (Some(role), Some(r)) => (role, r),
+            _ => continue,
+        };
+        let entry = deps.entry(r.name).or_insert((role, r.version));
+        entry.0 = entry.0.min(role);
+    }
+}
+
+fn conan_lock_deps(lock: &serde_json::Value, deps: &mut BTreeMap<String, (DepRole, String)>) {
+    let mut refs = vec![];
+    for (key, role) in [
+        ("requires", DepRole::Runtime),
+        ("build_requires", DepRole::Dev),
+        ("python_requires", DepRole::Dev),
+    ] {
+        for r in lock.get(key).and_then(|r| r.as_array()).into_iter().flatten() {
+            refs.extend(r.as_str().map(|r| (role, r)));
+        }
+    }
+    let nodes = lock.get("graph_lock").and_then(|g| g.get("nodes")).and_then(|n| n.as_object());
+    for node in nodes.into_iter().flat_map(|n| n.values()) {
+        refs.extend(node.get("ref").and_then(|r| r.as_str()).map(|r| (DepRole::Runtime, r)));
+    }
+    for (role, r) in refs {
+        if let Some(r) = parse_conan_ref(r) {
+            let entry = deps.entry(r.name).or_insert((role, r.version));
+            entry.0 = entry.0.min(role);
+        }
+    }
+}
+
+fn recipe_attrs(text: &str) -> (Option<String>, Vec<String>) {
+    let parse = RecipeAttrFromRegex::new();
+    let mut name = None;
+    let mut homepage = vec![];
+    let mut url = vec![];
+    for line in text.lines() {
+        match parse.parse(line) {
+            Ok(a) if a.name == "name" => name = name.or(Some(a.value)),
+            Ok(a) if a.name == "homepage" => homepage.push(a.value),
+            Ok(a) => url.push(a.value),
+            Err(_) => { },
+        }
+    }
+
+    // Prefer the homepage since ConanCenter recipes all set `url` to the recipe index
+    let candidates = homepage.into_iter().chain(url).filter(|u| !u.starts_with(CONAN_CENTER_INDEX)).collect();
+    (name, candidates)
+}
+
+fn conan_cache_recipes(log: &Logger) -> BTreeMap<String, Vec<(PathBuf, Vec<String>)>> {
+    let mut paths = vec![];
+    let conan1 = env::var_os("CONAN_USER_HOME").map(PathBuf::from).or_else(home_dir).map(|h| h.join(".conan/data"));
+    for name_dir in conan1.into_iter().flat_map(|d| fs::read_dir(d).into_iter().flatten().flatten()) {
+        // <name>/<version>/<user>/<channel>/export/conanfile.py
+        for version_dir in fs::read_dir(name_dir.path()).into_iter().flatten().flatten() {
+            for user_dir in fs::read_dir(version_dir.path()).into_iter().flatten().flatten() {
+                for channel_dir in fs::read_dir(user_dir.path()).into_iter().flatten().flatten() {
+                    paths.push(channel_dir.path().join("export/conanfile.py"));
+                }
+            }
+        }
+    }
+    let conan2 = env::var_os("CONAN_HOME").map(PathBuf::from).or_else(|| home_dir().map(|h| h.join(".conan2")));
+    for dir in conan2.into_iter().flat_map(|d| fs::read_dir(d.join("p")).into_iter().flatten().flatten()) {
+        paths.push(dir.path().join("e/conanfile.py"));
+    }
+    let mut out: BTreeMap<String, Vec<(PathBuf, Vec<String>)>> = BTreeMap::new();
+    for path in paths {
+        let text = match read_text(log, &path) {
+            Some(t) => t,
+            None => continue,
+        };
+        let (name, candidates) = recipe_attrs(&text);
+
+        // Conan 1 recipes don't always set `name` but it's part of the path
+        let name = name.or_else(|| {
+            path.ancestors().nth(5).and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string())
+        });
+        if let Some(name) = name {
+            out.entry(name).or_default().push((path, candidates));
+        }
+    }
+    out
+}
+
+async fn conan_center_urls(log: &Logger, ctx: &Context, name: &str, version: &str) -> Result<Vec<String>> {
+    #[derive(Deserialize)]
+    struct RecipeVersion {
+        folder: String,
+    }
+
+    #[derive(Deserialize)]
+    struct RecipeConfig {
+        versions: BTreeMap<String, RecipeVersion>,
+    }
+
+    let cache_key = format!("cpp-conan-{}-{}", name, version);
+    if let Some(c) = ctx.cache_get(log, &cache_key).await {
+        return Ok(c);
+    }
+    let raw = "https://raw.githubusercontent.com/conan-io/conan-center-index/master/recipes";
+    let resp = ctx.http_get(&format!("{}/{}/config.yml", raw, name)).await?.send().await?;
+    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+        vec![]
+    } else {
+        let config: RecipeConfig = serde_yaml::from_str(&resp.error_for_status()?.text().await?)?;
+
+        // Versions that aren't listed (ranges, etc.) use the latest recipe folder
+        let latest = config.versions.iter().max_by_key(|(v, _)| version_key(v)).map(|(_, r)| r);
+        let folder = match config.versions.get(version).or(latest) {
+            Some(v) => v.folder.clone(),
+            None => "all".to_string(),
+        };
+        let resp = ctx.http_get(&format!("{}/{}/{}/conanfile.py", raw, name, folder)).await?.send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            vec![]
+        } else {
+            recipe_attrs(&resp.error_for_status()?.text().await?).1
+        }
+    };
+    ctx.cache_put(log, &cache_key, &candidates).await;
+    Ok(candidates)
+}
+
+pub fn process_cpp_conan(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let mut deps = BTreeMap::new();
+    let txt_path = path.join("conanfile.txt");
+    let has_txt = match read_text(log, &txt_path) {
+        Some(text) => {
+            conanfile_txt_deps(&text, &mut deps);
+            true
+        },
+        None => false,
+    };
+
+    // The lockfile pins the whole graph, so it's only used for indirect dependencies or without a `conanfile.txt`
+    if ctx.supercontext.include_indirect || !has_txt {
+        let lock_path = path.join("conan.lock");
+        if let Some(text) = read_text(log, &lock_path) {
+            match serde_json::from_str(&text) {
+                Ok(lock) => conan_lock_deps(&lock, &mut deps),
+                Err(e) => warn!(
+                    log,
+                    "Error parsing conan.lock";
+                    "file" => lock_path.to_string_lossy().to_string(),
+                    "err" => #? e
+                ),
+            }
+        }
+    }
+    if deps.is_empty() {
+        return;
+    }
+    let cache = conan_cache_recipes(log);
+    for (name, (role, version)) in deps {
+        let ctx = ctx.with_role(role);
+        if let Some(recipes) = cache.get(&name) {
+            // Prefer the recipe exported for the locked version
+            let recipe =
+                recipes
+                    .iter()
+                    .find(|(p, _)| p.components().any(|c| c.as_os_str() == version.as_str()))
+                    .unwrap_or(&recipes[0]);
+            if !recipe.1.is_empty() {
+                process_dep_candidates(log, &ctx, pool, &name, recipe.1.clone());
+                continue;
+            }
+        }
+        let log = log.new(o!("dep" => name.clone()));
+        pool.push(spawn(async move {
+            match aes!({
+                let candidates = conan_center_urls(&log, &ctx, &name, &version).await?;
+                for c in &candidates {
+                    if ctx.maybe_add_url(&log, c).await {
+                        return Ok(());
+                    }
+                }
+                warn!(log, "No repo-ish url found in recipe");
+                Ok(())
+            }).await {
+                Ok(_) => { },
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error processing dependency";
+                        "err" => #? e
+                    );
+                },
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cmake_calls_nested_parens_and_comments() {
+        let text = r#"
+vcpkg_from_github(
+    OUT_SOURCE_PATH SOURCE_PATH
+    REPO fmtlib/fmt # upstream (not a fork)
+    REF "${VERSION}"
+    OPTIONS -DFOO=$<IF:$<BOOL:(x)>,a,b>
+    HEAD_REF master
+)
+vcpkg_from_gitlab(GITLAB_URL https://gitlab.example.com REPO "group/lib")
+"#;
+        let calls = cmake_calls(text, "vcpkg_from_github");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(cmake_arg(&calls[0], "REPO"), Some("fmtlib/fmt"));
+        assert_eq!(cmake_arg(&calls[0], "HEAD_REF"), Some("master"));
+        let calls = cmake_calls(text, "vcpkg_from_gitlab");
+        assert_eq!(cmake_arg(&calls[0], "GITLAB_URL"), Some("https://gitlab.example.com"));
+        assert_eq!(cmake_arg(&calls[0], "REPO"), Some("group/lib"));
+    }
+
+    #[test]
+    fn conanfile_txt_sections() {
+        let mut deps = BTreeMap::new();
+        let text = "[requires]\nzlib/1.2.13@user/channel#rev\n[tool_requires]\ncmake/3.27.0 # build\n";
+        conanfile_txt_deps(text, &mut deps);
+        assert_eq!(deps.len(), 2);
+        assert!(matches!(&deps["zlib"], (DepRole::Runtime, v) if v == "1.2.13"));
+        assert!(matches!(&deps["cmake"], (DepRole::Dev, v) if v == "3.27.0"));
+    }
+
+    #[test]
+    fn recipe_attrs_prefer_homepage() {
+        let (name, candidates) = recipe_attrs(r#"
+class ZlibConan(ConanFile):
+    name = "zlib"
+    url = "https://github.com/conan-io/conan-center-index"
+    homepage = "https://zlib.net"
+"#);
+        assert_eq!(name.as_deref(), Some("zlib"));
+        assert_eq!(candidates, vec!["https://zlib.net"]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+};
+use reqwest::StatusCode;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        DepRole,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+const PUB_DEV: &str = "https://pub.dev";
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    #[serde(default)]
+    dependency: String,
+    #[serde(default)]
+    description: serde_yaml::Value,
+    #[serde(default)]
+    source: String,
+}
+
+#[derive(Deserialize)]
+struct Lock {
+    #[serde(default)]
+    packages: BTreeMap<String, LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PackageSource {
+    candidates: Vec<String>,
+    funding: Vec<String>,
+}
+
+fn process_hosted_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, host: String, name: String) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("dart-{}-{}", host, name);
+            let source = match ctx.cache_get::<PackageSource>(&log, &cache_key).await {
+                Some(s) => s,
+                None => {
+                    #[derive(Deserialize)]
+                    struct Pubspec {
+                        repository: Option<String>,
+                        homepage: Option<String>,
+                        #[serde(default)]
+                        funding: Vec<String>,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Version {
+                        pubspec: Pubspec,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Package {
+                        latest: Version,
+                    }
+
+                    let resp =
+                        ctx
+                            .http_get(&format!("{}/api/packages/{}", host.trim_end_matches('/'), name))
+                            .await?
+                            .send()
+                            .await?;
+                    let source = if resp.status() == StatusCode::NOT_FOUND {
+                        PackageSource::default()
+                    } else {
+                        let pubspec = resp.error_for_status()?.json::<Package>().await?.latest.pubspec;
+                        PackageSource {
+                            candidates: pubspec.repository.into_iter().chain(pubspec.homepage).collect(),
+                            funding: pubspec.funding,
+                        }
+                    };
+                    ctx.cache_put(&log, &cache_key, &source).await;
+                    source
+                },
+            };
+            for c in &source.candidates {
+                if ctx.maybe_add_funded_url(&log, c, &source.funding).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+pub fn process_dart_pub(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("pubspec.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock: Lock = match maybe_read(&lock_path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_yaml::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading pubspec.lock";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    for (name, package) in lock.packages {
+        let role = match package.dependency.as_str() {
+            "direct dev" => DepRole::Dev,
+            "transitive" if !ctx.supercontext.include_indirect => continue,
+            _ => DepRole::Runtime,
+        };
+        let ctx = ctx.with_role(role);
+        let description_field =
+            |field: &str| package.description.get(field).and_then(|v| v.as_str()).map(|s| s.to_string());
+        match package.source.as_str() {
+            "hosted" => {
+                let host = description_field("url").unwrap_or_else(|| PUB_DEV.to_string());
+
+                // pub.dartlang.org is the old name for pub.dev
+                let host = if host.contains("pub.dartlang.org") {
+                    PUB_DEV.to_string()
+                } else {
+                    host
+                };
+                let name = description_field("name").unwrap_or(name);
+                process_hosted_dep(&log, &ctx, pool, host, name);
+            },
+            "git" => match description_field("url").or_else(|| package.description.as_str().map(|s| s.to_string())) {
+                Some(url) => process_git_dep(&log, &ctx, pool, url),
+                None => warn!(log, "Git dependency missing url"; "dep" => name),
+            },
+
+            // Path and sdk dependencies are local
+            _ => { },
+        }
+    }
+}
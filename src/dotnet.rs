@@ -0,0 +1,376 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        home_dir,
+        maybe_read,
+        version_key,
+        xml_nodes,
+        xml_text,
+    },
+    aes,
+};
+
+const PROJECT_EXTENSIONS: &[&str] = &["csproj", "fsproj", "vbproj"];
+
+fn msbuild_items(bytes: &[u8], items: &[&str]) -> Result<Vec<(String, Option<String>)>> {
+    let package = sxd_document::parser::parse(String::from_utf8_lossy(bytes).as_ref())?;
+    let document = package.as_document();
+    let root: sxd_xpath::nodeset::Node = document.root().into();
+    let factory = sxd_xpath::Factory::new();
+    let xctx = sxd_xpath::Context::new();
+    let text = |node: sxd_xpath::nodeset::Node, xpath: &str| xml_text(&factory, &xctx, node, xpath);
+    let mut out = vec![];
+    // Old-style projects use the MSBuild namespace, SDK-style projects don't
+    for item in items {
+        for node in xml_nodes(&factory, &xctx, root, &format!("//*[local-name()='{}']", item)) {
+            let id = text(node, "@Include");
+            if id.is_empty() {
+                continue;
+            }
+            let version =
+                [
+                    text(node, "@Version"),
+                    text(node, "*[local-name()='Version']"),
+                    text(node, "@VersionOverride"),
+                ]
+                    .into_iter()
+                    .find(|v| !v.is_empty());
+            out.push((id, version));
+        }
+    }
+    Ok(out)
+}
+
+fn load_msbuild_items(log: &Logger, path: &Path, items: &[&str]) -> Vec<(String, Option<String>)> {
+    let res = match maybe_read(path) {
+        Ok(Some(b)) => msbuild_items(&b, items),
+        Ok(None) => return vec![],
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(r) => r,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading MSBuild file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            vec![]
+        },
+    }
+}
+
+fn lockfile_deps(log: &Logger, path: &Path, include_indirect: bool) -> Vec<(String, Option<String>)> {
+    #[derive(Deserialize)]
+    struct Locked {
+        #[serde(rename = "type")]
+        type_: String,
+        resolved: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Lock {
+        dependencies: BTreeMap<String, BTreeMap<String, Locked>>,
+    }
+
+    let lock: Lock = match maybe_read(path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(l)) => l,
+        Ok(None) => return vec![],
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading packages.lock.json";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            return vec![];
+        },
+    };
+    let mut out = vec![];
+    for packages in lock.dependencies.into_values() {
+        for (id, locked) in packages {
+            match locked.type_.as_str() {
+                "Direct" | "CentralTransitive" => { },
+                "Transitive" if include_indirect => { },
+                _ => continue,
+            }
+            out.push((id, locked.resolved));
+        }
+    }
+    out
+}
+
+fn discover_projects(log: &Logger, path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![];
+    for e in fs::read_dir(path).into_iter().flatten().flatten() {
+        let p = e.path();
+        let ext = p.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        if PROJECT_EXTENSIONS.contains(&ext.as_str()) {
+            out.push(p);
+            continue;
+        }
+        if ext != "sln" {
+            continue;
+        }
+        let text = match maybe_read(&p) {
+            Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading solution";
+                    "file" => p.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+                continue;
+            },
+        };
+
+        // Project("{type-guid}") = "Name", "relative\path.csproj", "{guid}"
+        for line in text.lines() {
+            if !line.starts_with("Project(") {
+                continue;
+            }
+            let rel = match line.split('"').nth(5) {
+                Some(r) => r.replace('\\', "/"),
+                None => continue,
+            };
+            if PROJECT_EXTENSIONS.iter().any(|e| rel.ends_with(&format!(".{}", e))) {
+                out.push(path.join(rel));
+            }
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn central_versions(log: &Logger, root: &Path, dir: &Path) -> HashMap<String, String> {
+    let mut dir = Some(dir);
+    while let Some(d) = dir {
+        let props = d.join("Directory.Packages.props");
+        if props.exists() {
+            return load_msbuild_items(log, &props, &["PackageVersion"])
+                .into_iter()
+                .filter_map(|(id, v)| Some((id.to_lowercase(), v?)))
+                .collect();
+        }
+        if d == root {
+            break;
+        }
+        dir = d.parent();
+    }
+    HashMap::new()
+}
+
+fn nuget_packages_dir() -> Option<PathBuf> {
+    env::var_os("NUGET_PACKAGES").map(PathBuf::from).or_else(|| home_dir().map(|h| h.join(".nuget/packages")))
+}
+
+fn local_nuspec_urls(id: &str, version: Option<&str>) -> Option<Vec<String>> {
+    let id_dir = nuget_packages_dir()?.join(id);
+    let version_dir = match version.map(|v| id_dir.join(v.to_lowercase())).filter(|d| d.exists()) {
+        Some(d) => d,
+        None => {
+            let mut versions: Vec<PathBuf> =
+                fs::read_dir(&id_dir).ok()?.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect();
+            versions.sort_by_cached_key(|p| version_key(&p.file_name().unwrap().to_string_lossy()));
+            versions.pop()?
+        },
+    };
+    let bytes = maybe_read(&version_dir.join(format!("{}.nuspec", id))).ok()??;
+    let package = sxd_document::parser::parse(String::from_utf8_lossy(&bytes).as_ref()).ok()?;
+    let document = package.as_document();
+    let root: sxd_xpath::nodeset::Node = document.root().into();
+    let factory = sxd_xpath::Factory::new();
+    let xctx = sxd_xpath::Context::new();
+    let text = |xpath: &str| xml_text(&factory, &xctx, root, xpath);
+    Some(
+        [
+            text("//*[local-name()='metadata']/*[local-name()='repository']/@url"),
+            text("//*[local-name()='metadata']/*[local-name()='projectUrl']"),
+        ]
+            .into_iter()
+            .filter(|u| !u.is_empty())
+            .collect(),
+    )
+}
+
+fn process_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, id: String, version: Option<String>) {
+    let log = log.new(o!("dep" => id.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            if let Some(candidates) = local_nuspec_urls(&id, version.as_deref()) {
+                for c in candidates {
+                    if ctx.maybe_add_url(&log, &c).await {
+                        return Ok(());
+                    }
+                }
+            }
+            let cache_key = format!("dotnet-nuget-{}", id);
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    #[derive(Deserialize)]
+                    struct CatalogEntry {
+                        #[serde(rename = "projectUrl")]
+                        project_url: Option<String>,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Leaf {
+                        #[serde(rename = "catalogEntry")]
+                        catalog_entry: CatalogEntry,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Page {
+                        #[serde(rename = "@id")]
+                        id: String,
+                        items: Option<Vec<Leaf>>,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Index {
+                        items: Vec<Page>,
+                    }
+
+                    let resp =
+                        ctx
+                            .http_get(&format!("https://api.nuget.org/v3/registration5-gz-semver2/{}/index.json", id))
+                            .await?
+                            .send()
+                            .await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        // Pages are in version order; large packages only link the page rather than inlining it
+                        let index: Index = resp.error_for_status()?.json().await?;
+                        let leaves = match index.items.into_iter().last() {
+                            Some(Page { items: Some(items), .. }) => items,
+                            Some(Page { id: page, .. }) => {
+                                ctx
+                                    .http_get(&page)
+                                    .await?
+                                    .send()
+                                    .await?
+                                    .error_for_status()?
+                                    .json::<Page>()
+                                    .await?
+                                    .items
+                                    .unwrap_or_default()
+                            },
+                            None => vec![],
+                        };
+                        leaves.into_iter().last().and_then(|l| l.catalog_entry.project_url).into_iter().collect()
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+pub fn process_dotnet_nuget(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let include_indirect = ctx.supercontext.include_indirect;
+
+    // Keyed by lowercased id since NuGet ids are case insensitive (and the local cache uses lowercase)
+    let mut deps: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut add = |id: String, version: Option<String>| {
+        let entry = deps.entry(id.to_lowercase()).or_default();
+        if entry.is_none() {
+            *entry = version;
+        }
+    };
+    let root_central = path.join("Directory.Packages.props");
+    for (id, version) in load_msbuild_items(log, &root_central, &["GlobalPackageReference"]) {
+        add(id, version);
+    }
+    for project in discover_projects(log, path) {
+        let dir = project.parent().unwrap_or(path);
+        let central = central_versions(log, path, dir);
+        for (id, version) in load_msbuild_items(log, &project, &["PackageReference"]) {
+            let version = version.or_else(|| central.get(&id.to_lowercase()).cloned());
+            add(id, version);
+        }
+        for (id, version) in lockfile_deps(log, &dir.join("packages.lock.json"), include_indirect) {
+            add(id, version);
+        }
+    }
+    for (id, version) in deps {
+        process_dep(log, ctx, pool, id, version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msbuild_items_with_and_without_namespace() {
+        let sdk = br#"<Project Sdk="Microsoft.NET.Sdk">
+  <ItemGroup>
+    <PackageReference Include="Serilog" Version="3.1.1" />
+    <PackageReference Include="Dapper"><Version>2.1.0</Version></PackageReference>
+    <PackageReference Include="Polly" />
+  </ItemGroup>
+</Project>"#;
+        assert_eq!(msbuild_items(sdk, &["PackageReference"]).unwrap(), vec![
+            ("Serilog".to_string(), Some("3.1.1".to_string())),
+            ("Dapper".to_string(), Some("2.1.0".to_string())),
+            ("Polly".to_string(), None),
+        ]);
+        let old = br#"<Project xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+  <ItemGroup><PackageReference Include="NUnit" Version="3.13.3" /></ItemGroup>
+</Project>"#;
+        assert_eq!(msbuild_items(old, &["PackageReference"]).unwrap(), vec![
+            ("NUnit".to_string(), Some("3.13.3".to_string())),
+        ]);
+    }
+}
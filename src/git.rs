@@ -0,0 +1,228 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::task::JoinHandle;
+use crate::common::{
+    Context,
+    DepRole,
+    maybe_read,
+    process_git_dep,
+};
+
+// `[submodule "x"]` is section `submodule`, subsection `x`; keys are lowercased
+struct ConfigEntry {
+    section: String,
+    subsection: Option<String>,
+    key: String,
+    value: String,
+}
+
+fn parse_git_config(text: &str) -> Vec<ConfigEntry> {
+    let mut out = vec![];
+    let mut section = String::new();
+    let mut subsection = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.split(']').next()) {
+            let (s, sub) = match header.split_once(char::is_whitespace) {
+                Some((s, sub)) => (s, Some(sub.trim().trim_matches('"').to_string())),
+                None => (header, None),
+            };
+            section = s.to_lowercase();
+            subsection = sub;
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        out.push(ConfigEntry {
+            section: section.clone(),
+            subsection: subsection.clone(),
+            key: key.trim().to_lowercase(),
+            value: value.trim().trim_matches('"').to_string(),
+        });
+    }
+    out
+}
+
+fn read_text(log: &Logger, path: &Path) -> Option<String> {
+    match maybe_read(path) {
+        Ok(b) => b.map(|b| String::from_utf8_lossy(&b).to_string()),
+        Err(e) => {
+            warn!(
+                log,
+                "Error reading file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn git_dir(log: &Logger, path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let text = read_text(log, &dot_git)?;
+    let dir = text.trim().strip_prefix("gitdir:")?.trim();
+    Some(path.join(dir))
+}
+
+pub fn remote_url(log: &Logger, path: &Path) -> Option<String> {
+    let git_dir = git_dir(log, path)?;
+
+    // Worktrees keep the config in the main git dir
+    let config_dir = match read_text(log, &git_dir.join("commondir")) {
+        Some(c) => git_dir.join(c.trim()),
+        None => git_dir,
+    };
+    let config = parse_git_config(&read_text(log, &config_dir.join("config"))?);
+    let remotes: Vec<&ConfigEntry> = config.iter().filter(|e| e.section == "remote" && e.key == "url").collect();
+    let remote = remotes.iter().find(|e| e.subsection.as_deref() == Some("origin")).or(remotes.first())?;
+    Some(remote.value.clone())
+}
+
+fn resolve_relative(base: &str, relative: &str) -> String {
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut rest = relative;
+    loop {
+        if let Some(r) = rest.strip_prefix("./") {
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("../") {
+            rest = r;
+            // Keeping the `:` of an scp-style `host:path` url
+            let cut = base.rfind(['/', ':']).unwrap_or(0);
+            base.truncate(cut + usize::from(base[cut ..].starts_with(':')));
+        } else {
+            break;
+        }
+    }
+    if base.ends_with(':') {
+        format!("{}{}", base, rest)
+    } else {
+        format!("{}/{}", base, rest)
+    }
+}
+
+fn process_submodules(
+    log: &Logger,
+    ctx: &Context,
+    pool: &mut Vec<JoinHandle<()>>,
+    path: &Path,
+    remote: Option<String>,
+) {
+    let modules_path = path.join(".gitmodules");
+    let log = log.new(o!("file" => modules_path.to_string_lossy().to_string()));
+    let text = match read_text(&log, &modules_path) {
+        Some(t) => t,
+        None => return,
+    };
+    let entries = parse_git_config(&text);
+    let mut names: Vec<&str> = vec![];
+    for e in entries.iter().filter(|e| e.section == "submodule") {
+        if let Some(name) = e.subsection.as_deref().filter(|n| !names.contains(n)) {
+            names.push(name);
+        }
+    }
+    for name in names {
+        let field = |key: &str| {
+            entries
+                .iter()
+                .find(|e| e.section == "submodule" && e.subsection.as_deref() == Some(name) && e.key == key)
+                .map(|e| e.value.as_str())
+        };
+        let url = match field("url") {
+            Some(u) if u.starts_with("./") || u.starts_with("../") => match &remote {
+                Some(r) => resolve_relative(r, u),
+                None => {
+                    warn!(log, "Relative submodule url but no remote to resolve it against"; "submodule" => name);
+                    continue;
+                },
+            },
+            Some(u) => u.to_string(),
+            None => continue,
+        };
+
+        // Checked out submodules, whose relative urls are relative to the submodule's own url
+        if let Some(sub_path) = field("path") {
+            process_submodules(&log, ctx, pool, &path.join(sub_path), Some(url.clone()));
+        }
+        process_git_dep(&log, ctx, pool, url);
+    }
+}
+
+pub fn process_git_submodules(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    if !path.join(".gitmodules").exists() {
+        return;
+    }
+    let remote = remote_url(log, path);
+    process_submodules(log, &ctx.with_role(DepRole::Runtime), pool, path, remote);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_relative_urls() {
+        assert_eq!(resolve_relative("https://github.com/a/b.git", "../c.git"), "https://github.com/a/c.git");
+        assert_eq!(resolve_relative("https://github.com/a/b/", "../../x/c"), "https://github.com/x/c");
+        assert_eq!(resolve_relative("git@github.com:a/b.git", "../c.git"), "git@github.com:a/c.git");
+        assert_eq!(resolve_relative("git@example.com:b.git", "../c.git"), "git@example.com:c.git");
+        assert_eq!(resolve_relative("https://github.com/a/b", "./c"), "https://github.com/a/b/c");
+    }
+
+    #[test]
+    fn parse_gitmodules() {
+        let entries = parse_git_config(
+            "# comment\n[submodule \"lib/x\"]\n\tpath = lib/x\n\tURL = \"../x.git\"\n[core]\n\tbare = false\n",
+        );
+        let entries: Vec<String> =
+            entries
+                .iter()
+                .map(|e| format!("{}|{}|{}|{}", e.section, e.subsection.as_deref().unwrap_or(""), e.key, e.value))
+                .collect();
+        assert_eq!(entries, vec!["submodule|lib/x|path|lib/x", "submodule|lib/x|url|../x.git", "core||bare|false"]);
+    }
+
+    #[tokio::test]
+    async fn self_hosted_gitlab_submodules() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(".gitmodules"),
+            "[submodule \"drm\"]\n\tpath = drm\n\turl = ../drm.git\n[submodule \"glib\"]\n\tpath = glib\n\turl = \
+             https://gitlab.gnome.org/GNOME/sub/glib.git\n",
+        ).unwrap();
+        let log = Logger::root(slog::Discard, o!());
+        let ctx = Context::new(crate::common::Supercontext::new(std::env::temp_dir(), false));
+        let mut pool = vec![];
+        process_submodules(
+            &log,
+            &ctx,
+            &mut pool,
+            dir.path(),
+            Some("git@gitlab.freedesktop.org:mesa/mesa.git".to_string()),
+        );
+        for f in pool {
+            f.await.unwrap();
+        }
+        let mut projects: Vec<String> = ctx.config.lock().unwrap().projects.keys().cloned().collect();
+        projects.sort();
+        assert_eq!(
+            projects,
+            vec!["https://gitlab.freedesktop.org/mesa/drm", "https://gitlab.gnome.org/GNOME/sub/glib"],
+        );
+    }
+}
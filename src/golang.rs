@@ -1,114 +1,546 @@
+use anyhow::Result;
 use slog::{
     Logger,
+    debug,
     o,
     warn,
 };
 use std::{
+    collections::HashSet,
     path::Path,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+use tokio::{
+    process::Command,
+    spawn,
+    task::JoinHandle,
 };
-use structre::structre;
 use crate::{
-    bb,
+    aes,
     common::{
         Context,
         maybe_read,
     },
 };
 
-pub fn process_golang_gomod(log: &Logger, ctx: &Context, path: &Path) {
-    let path = path.join("go.mod");
-    let log = log.new(o!("file" => path.to_string_lossy().to_string()));
+// `{}` is the path element after the prefix
+const KNOWN_VANITY: &[(&str, &str)] = &[
+    ("golang.org/x/", "https://github.com/golang/{}"),
+    ("go.uber.org/", "https://github.com/uber-go/{}"),
+    ("k8s.io/", "https://github.com/kubernetes/{}"),
+    ("sigs.k8s.io/", "https://github.com/kubernetes-sigs/{}"),
+    ("go.etcd.io/", "https://github.com/etcd-io/{}"),
+    ("google.golang.org/grpc", "https://github.com/grpc/grpc-go"),
+    ("google.golang.org/protobuf", "https://github.com/protocolbuffers/protobuf-go"),
+    ("google.golang.org/api", "https://github.com/googleapis/google-api-go-client"),
+    ("google.golang.org/genproto", "https://github.com/googleapis/go-genproto"),
+    ("google.golang.org/appengine", "https://github.com/golang/appengine"),
+    ("cloud.google.com/go", "https://github.com/googleapis/google-cloud-go"),
+    ("go.opentelemetry.io/otel", "https://github.com/open-telemetry/opentelemetry-go"),
+    ("go.opentelemetry.io/contrib", "https://github.com/open-telemetry/opentelemetry-go-contrib"),
+    ("go.mongodb.org/mongo-driver", "https://github.com/mongodb/mongo-go-driver"),
+    ("honnef.co/go/tools", "https://github.com/dominikh/go-tools"),
+    ("gotest.tools", "https://github.com/gotestyourself/gotest.tools"),
+    ("gopkg.in/yaml.", "https://github.com/go-yaml/yaml"),
+];
+
+// GitLab allows nested groups, so like `go` it needs the go-import metadata
+const CODE_HOSTS: &[&str] = &["github.com", "bitbucket.org"];
+
+const VCS_SUFFIXES: &[&str] = &[".git", ".hg", ".bzr", ".svn", ".fossil"];
 
-    #[structre(r#"^\s*(?P<keyword>[^\s]+)\s+(?P<remainder>.*)\s*$"#)]
-    struct Keyword {
-        keyword: String,
-        remainder: String,
+fn static_repo(module: &str) -> Option<String> {
+    let parts = module.split('/').collect::<Vec<_>>();
+    if CODE_HOSTS.contains(&parts[0]) {
+        if parts.len() < 3 {
+            return None;
+        }
+        return Some(format!("https://{}", parts[..3].join("/")));
+    }
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        if let Some(suffix) = VCS_SUFFIXES.iter().find(|s| part.ends_with(*s)) {
+            let mut root = parts[..i + 1].join("/");
+            root.truncate(root.len() - suffix.len());
+            return Some(format!("https://{}", root));
+        }
     }
+    None
+}
 
-    let parse_keyword = KeywordFromRegex::new();
+fn known_vanity_repo(module: &str) -> Option<String> {
+    for (prefix, repo) in KNOWN_VANITY {
+        let rest = match module.strip_prefix(prefix) {
+            Some(r) => r,
+            None => continue,
+        };
+        let at_boundary = prefix.ends_with(['/', '.']) || rest.is_empty() || rest.starts_with('/');
+        if !at_boundary {
+            continue;
+        }
+        if repo.contains("{}") {
+            let name = rest.split('/').next().unwrap();
+            if name.is_empty() {
+                continue;
+            }
+            return Some(repo.replace("{}", name));
+        }
+        return Some(repo.to_string());
+    }
 
-    #[structre(r#"^\s*(?P<id>[^\s]+)\s+(?:[^\s]+)(?:\s+//\s+(?P<comment>[^\s]+))?\s*$"#)]
-    struct Require {
-        id: String,
-        comment: String,
+    // gopkg.in/pkg.v1 is github.com/go-pkg/pkg, gopkg.in/user/pkg.v1 is github.com/user/pkg
+    if let Some(rest) = module.strip_prefix("gopkg.in/") {
+        let mut parts = rest.split('/');
+        let first = parts.next()?;
+        return match parts.next() {
+            Some(second) if !first.contains(".v") => {
+                Some(format!("https://github.com/{}/{}", first, second.split(".v").next().unwrap()))
+            },
+            _ => {
+                let name = first.split(".v").next().unwrap();
+                Some(format!("https://github.com/go-{}/{}", name, name))
+            },
+        };
     }
+    None
+}
+
+async fn fetch_vanity_repo(ctx: &Context, module: &str) -> Result<Option<String>> {
+    let text = ctx.http_get_html(&format!("https://{}?go-get=1", module)).await?;
+    Ok(vanity_repo(module, &text))
+}
 
-    let parse_require = RequireFromRegex::new();
-    let mut parens = 0;
-    let mut in_require = false;
-    let bytes = match maybe_read(&path) {
-        Ok(None) => return,
+fn vanity_repo(module: &str, html: &str) -> Option<String> {
+    let page = scraper::Html::parse_document(html);
+    let mut import_repo = None;
+    let mut source_repo = None;
+    for meta in page.select(&scraper::Selector::parse("meta").unwrap()) {
+        let name = meta.value().attr("name").unwrap_or_default();
+        let content = meta.value().attr("content").unwrap_or_default().split_whitespace().collect::<Vec<_>>();
+        let prefix = match content.first() {
+            Some(p) => *p,
+            None => continue,
+        };
+        if module != prefix && !module.starts_with(&format!("{}/", prefix)) {
+            continue;
+        }
+        match (name, content.as_slice()) {
+            // Go 1.21 added an optional fourth subdirectory field
+            ("go-import", [_, vcs, repo, ..]) if *vcs != "mod" => import_repo = Some(repo.to_string()),
+            ("go-source", [_, home, ..]) if *home != "_" => source_repo = Some(home.to_string()),
+            _ => { },
+        }
+    }
+    import_repo.or(source_repo)
+}
+
+fn process_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, module: String) {
+    let log = log.new(o!("dep" => module.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let repo = if let Some(r) = static_repo(&module) {
+                r
+            } else if let Some(r) = known_vanity_repo(&module) {
+                r
+            } else {
+                let cache_key = format!("golang-{}", module);
+                let repo = match ctx.cache_get::<Option<String>>(&log, &cache_key).await {
+                    Some(r) => r,
+                    None => {
+                        let repo = fetch_vanity_repo(&ctx, &module).await?;
+                        ctx.cache_put(&log, &cache_key, &repo).await;
+                        repo
+                    },
+                };
+                match repo {
+                    Some(r) => r,
+                    None => {
+                        warn!(log, "No go-import or go-source meta tags found for module");
+                        return Ok(());
+                    },
+                }
+            };
+            // Already a repo root, and `maybe_add_url` would truncate nested GitLab groups
+            ctx.add_url(repo.trim_end_matches('/').trim_end_matches(".git")).await;
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn tokenize_line(line: &str) -> (Vec<String>, String) {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+        let mut token = String::new();
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.next();
+                return (tokens, chars.collect::<String>().trim().to_string());
+            },
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => token.extend(chars.next()),
+                        '"' => break,
+                        c => token.push(c),
+                    }
+                }
+            },
+            '`' => {
+                for c in chars.by_ref() {
+                    if c == '`' {
+                        break;
+                    }
+                    token.push(c);
+                }
+            },
+            c => {
+                token.push(c);
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    token.push(*c);
+                    chars.next();
+                }
+            },
+        }
+        tokens.push(token);
+    }
+    (tokens, String::new())
+}
+
+struct Directive {
+    verb: String,
+    args: Vec<String>,
+    comment: String,
+}
+
+fn parse_directives(text: &str) -> Vec<Directive> {
+    let mut out = vec![];
+    let mut block: Option<String> = None;
+    for line in text.lines() {
+        let (mut tokens, comment) = tokenize_line(line);
+        if tokens.is_empty() {
+            continue;
+        }
+        if block.is_some() && tokens.len() == 1 && tokens[0] == ")" {
+            block = None;
+            continue;
+        }
+        if let Some(verb) = &block {
+            out.push(Directive {
+                verb: verb.clone(),
+                args: tokens,
+                comment,
+            });
+            continue;
+        }
+        let verb = tokens.remove(0);
+        if tokens.len() == 1 && tokens[0] == "(" {
+            block = Some(verb);
+            continue;
+        }
+        out.push(Directive {
+            verb,
+            args: tokens,
+            comment,
+        });
+    }
+    out
+}
+
+struct Require {
+    path: String,
+    version: String,
+    indirect: bool,
+}
+
+#[derive(Clone)]
+struct Replace {
+    old_path: String,
+    old_version: Option<String>,
+    // `None` for a local directory
+    new_path: Option<String>,
+}
+
+#[derive(Default)]
+struct GoMod {
+    module: Option<String>,
+    requires: Vec<Require>,
+    replaces: Vec<Replace>,
+}
+
+#[derive(Default)]
+struct GoWork {
+    uses: Vec<String>,
+    replaces: Vec<Replace>,
+}
+
+fn parse_replace(args: &[String]) -> Option<Replace> {
+    let arrow = args.iter().position(|a| a == "=>")?;
+    let (old, new) = (&args[..arrow], &args[arrow + 1..]);
+    let new_path = new.first()?;
+    Some(Replace {
+        old_path: old.first()?.clone(),
+        old_version: old.get(1).cloned(),
+        new_path: if new_path.starts_with("./") || new_path.starts_with("../") || new_path.starts_with('/') {
+            None
+        } else {
+            Some(new_path.clone())
+        },
+    })
+}
+
+fn parse_gomod(text: &str) -> GoMod {
+    let mut out = GoMod::default();
+    for d in parse_directives(text) {
+        match d.verb.as_str() {
+            "module" => out.module = d.args.into_iter().next(),
+            "require" => {
+                if let [path, version] = d.args.as_slice() {
+                    out.requires.push(Require {
+                        path: path.clone(),
+                        version: version.clone(),
+                        indirect: d.comment.split(';').any(|c| c.trim() == "indirect"),
+                    });
+                }
+            },
+            "replace" => out.replaces.extend(parse_replace(&d.args)),
+
+            // `exclude` and `retract` don't change where a module lives
+            _ => { },
+        }
+    }
+    out
+}
+
+fn parse_gowork(text: &str) -> GoWork {
+    let mut out = GoWork::default();
+    for d in parse_directives(text) {
+        match d.verb.as_str() {
+            "use" => out.uses.extend(d.args.into_iter().next()),
+            "replace" => out.replaces.extend(parse_replace(&d.args)),
+            _ => { },
+        }
+    }
+    out
+}
+
+async fn transitive_modules(log: &Logger, dir: &Path) -> Vec<(String, String)> {
+    let mut out = vec![];
+    match Command::new("go").args(["mod", "graph"]).current_dir(dir).output().await {
+        Ok(res) if res.status.success() => {
+            for line in String::from_utf8_lossy(&res.stdout).lines() {
+                if let Some((path, version)) = line.split_whitespace().nth(1).and_then(|m| m.split_once('@')) {
+                    out.push((path.to_string(), version.to_string()));
+                }
+            }
+            return out;
+        },
+        Ok(res) => debug!(log, "go mod graph failed, falling back to go.sum"; "output" => #? res),
+        Err(e) => debug!(log, "Unable to run go mod graph, falling back to go.sum"; "err" => #? e),
+    }
+    let sum_path = dir.join("go.sum");
+    match maybe_read(&sum_path) {
+        Ok(None) => { },
+        Ok(Some(b)) => {
+            for line in String::from_utf8_lossy(&b).lines() {
+                let mut parts = line.split_whitespace();
+                if let (Some(path), Some(version)) = (parts.next(), parts.next()) {
+                    out.push((path.to_string(), version.trim_end_matches("/go.mod").to_string()));
+                }
+            }
+        },
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading go.sum";
+                "file" => sum_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+        },
+    }
+    out
+}
+
+fn apply_replaces(replaces: &[&Replace], path: String, version: &str) -> Option<String> {
+    let r = replaces.iter().rev().find(|r| {
+        r.old_path == path && r.old_version.as_ref().map(|v| v == version).unwrap_or(true)
+    });
+    match r {
+        Some(r) => r.new_path.clone(),
+        None => Some(path),
+    }
+}
+
+fn resolve_module(
+    local_modules: &HashSet<String>,
+    replaces: &[&Replace],
+    module: String,
+    version: &str,
+) -> Option<String> {
+    if local_modules.contains(&module) {
+        return None;
+    }
+    apply_replaces(replaces, module, version)
+}
+
+pub fn process_golang_gomod(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let work_path = path.join("go.work");
+    let work = match maybe_read(&work_path) {
+        Ok(None) => None,
+        Ok(Some(b)) => Some(parse_gowork(&String::from_utf8_lossy(&b))),
         Err(e) => {
             warn!(
                 log,
-                "Error loading go.mod";
+                "Error loading go.work";
+                "file" => work_path.to_string_lossy().to_string(),
                 "err" => #? e
             );
             return;
         },
-        Ok(Some(b)) => b,
     };
-    let lines = String::from_utf8_lossy(&bytes);
-    let mut config = ctx.config.lock().unwrap();
-    for line in lines.lines() {
-        if parens == 0 {
-            if let Ok(kw) = parse_keyword.parse(&line) {
-                if kw.remainder.chars().next().unwrap_or(' ') == '(' {
-                    parens += 1;
-                    if kw.keyword == "require" {
-                        in_require = true;
-                    }
-                } else if kw.keyword == "require" {
-                    bb!({
-                        let require = match parse_require.parse(&kw.remainder) {
-                            Ok(require) => {
-                                if require.comment == "indirect" {
-                                    break;
-                                }
-                                require
-                            },
-                            Err(e) => {
-                                warn!(
-                                    log,
-                                    "Error parsing require line";
-                                    "line" => line,
-                                    "err" => #? e
-                                );
-                                break;
-                            },
-                        };
-                        config.projects.insert(format!("https://{}", require.id), None);
-                    });
-                }
+    let dirs = match &work {
+        Some(w) => w.uses.iter().map(|u| path.join(u)).collect(),
+        None => vec![path.to_path_buf()],
+    };
+    let mut mods = vec![];
+    for dir in dirs {
+        let mod_path = dir.join("go.mod");
+        let log = log.new(o!("file" => mod_path.to_string_lossy().to_string()));
+        match maybe_read(&mod_path) {
+            Ok(None) => { },
+            Ok(Some(b)) => mods.push((log, dir, parse_gomod(&String::from_utf8_lossy(&b)))),
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading go.mod";
+                    "err" => #? e
+                );
+            },
+        }
+    }
+
+    // Workspace replacements override module replacements
+    let work_replaces = work.iter().flat_map(|w| &w.replaces).collect::<Vec<_>>();
+    let local_modules = mods.iter().filter_map(|m| m.2.module.clone()).collect::<HashSet<_>>();
+    let include_indirect = ctx.supercontext.include_indirect;
+    let seen = Arc::new(Mutex::new(HashSet::new()));
+    for (log, dir, gomod) in &mods {
+        let replaces = gomod.replaces.iter().chain(work_replaces.iter().copied()).collect::<Vec<_>>();
+        for r in &gomod.requires {
+            if r.indirect && !include_indirect {
+                continue;
             }
-        } else {
-            if line == ")" {
-                parens -= 1;
-                if in_require {
-                    in_require = false;
+            if let Some(module) = resolve_module(&local_modules, &replaces, r.path.clone(), &r.version) {
+                if seen.lock().unwrap().insert(module.clone()) {
+                    process_dep(log, ctx, pool, module);
                 }
-            } else if in_require {
-                bb!({
-                    let require = match parse_require.parse(&line) {
-                        Ok(require) => {
-                            if require.comment == "indirect" {
-                                break;
-                            }
-                            require
-                        },
-                        Err(e) => {
-                            warn!(
-                                log,
-                                "Error parsing require line";
-                                "line" => line,
-                                "err" => #? e
-                            );
-                            break;
-                        },
-                    };
-                    config.projects.insert(format!("https://{}", require.id), None);
-                });
             }
         }
+        if include_indirect {
+            // May download modules
+            let log = log.clone();
+            let ctx = ctx.clone();
+            let dir = dir.clone();
+            let replaces = replaces.into_iter().cloned().collect::<Vec<_>>();
+            let local_modules = local_modules.clone();
+            let seen = seen.clone();
+            pool.push(spawn(async move {
+                let replaces = replaces.iter().collect::<Vec<_>>();
+                let mut sub_pool = vec![];
+                for (module, version) in transitive_modules(&log, &dir).await {
+                    if let Some(module) = resolve_module(&local_modules, &replaces, module, &version) {
+                        if seen.lock().unwrap().insert(module.clone()) {
+                            process_dep(&log, &ctx, &mut sub_pool, module);
+                        }
+                    }
+                }
+                for f in sub_pool {
+                    f.await.unwrap();
+                }
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_line_quotes_and_comments() {
+        let (tokens, comment) = tokenize_line(r#"  "example.com/a b" `raw` v1.0.0 // indirect; x"#);
+        assert_eq!(tokens, vec!["example.com/a b", "raw", "v1.0.0"]);
+        assert_eq!(comment, "indirect; x");
+    }
+
+    #[test]
+    fn parse_gomod_blocks_and_replaces() {
+        let gomod = parse_gomod(
+            r#"
+module example.com/me
+
+require example.com/single v1.0.0
+require (
+    github.com/a/b v1.2.3
+    golang.org/x/text v0.3.0 // indirect
+)
+
+replace (
+    github.com/a/b v1.2.3 => github.com/fork/b v1.2.4
+    example.com/single => ../single
+)
+"#,
+        );
+        assert_eq!(gomod.module.as_deref(), Some("example.com/me"));
+        let requires: Vec<(&str, bool)> = gomod.requires.iter().map(|r| (r.path.as_str(), r.indirect)).collect();
+        assert_eq!(
+            requires,
+            vec![("example.com/single", false), ("github.com/a/b", false), ("golang.org/x/text", true)]
+        );
+        assert_eq!(gomod.replaces.len(), 2);
+        assert_eq!(gomod.replaces[0].old_version.as_deref(), Some("v1.2.3"));
+        assert_eq!(gomod.replaces[0].new_path.as_deref(), Some("github.com/fork/b"));
+        assert_eq!(gomod.replaces[1].new_path, None);
+    }
+
+    #[test]
+    fn parse_gowork_uses() {
+        let work = parse_gowork("go 1.21\n\nuse (\n    ./a\n    ./b\n)\n");
+        assert_eq!(work.uses, vec!["./a", "./b"]);
+    }
+
+    #[test]
+    fn vanity_repo_meta() {
+        let html = r#"<html><head>
+<meta name="go-import" content="example.com/other git https://git.example.com/other">
+<meta name="go-import" content="example.com/mono git https://git.example.com/mono.git sub/dir">
+</head></html>"#;
+        assert_eq!(vanity_repo("example.com/mono/pkg", html).as_deref(), Some("https://git.example.com/mono.git"));
+        assert_eq!(vanity_repo("example.com/missing", html), None);
+        let proxied = r#"<meta name="go-import" content="example.com/m mod https://proxy.example.com">
+<meta name="go-source" content="example.com/m https://github.com/a/m _ _">"#;
+        assert_eq!(vanity_repo("example.com/m", proxied).as_deref(), Some("https://github.com/a/m"));
     }
 }
@@ -0,0 +1,326 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use reqwest::StatusCode;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+// Stanzas are lowercased, and empty for top level fields
+struct CabalField {
+    stanza: String,
+    name: String,
+    value: String,
+}
+
+fn parse_cabal(text: &str) -> Vec<CabalField> {
+    let mut out: Vec<CabalField> = vec![];
+    let mut stanza = String::new();
+    let mut field_indent = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("--") {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if let Some(field_indent) = field_indent {
+            if indent > field_indent {
+                let field = out.last_mut().unwrap();
+                field.value.push(' ');
+                field.value.push_str(trimmed);
+                continue;
+            }
+        }
+        field_indent = None;
+        match trimmed.split_once(':') {
+            Some((name, value)) if !name.is_empty() &&
+                name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => {
+                if indent == 0 {
+                    stanza.clear();
+                }
+                field_indent = Some(indent);
+                out.push(CabalField {
+                    stanza: stanza.clone(),
+                    name: name.to_lowercase(),
+                    value: value.trim().to_string(),
+                });
+            },
+            _ => {
+                if indent == 0 {
+                    stanza = trimmed.to_lowercase();
+                }
+            },
+        }
+    }
+    out
+}
+
+fn build_depends(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split(',').filter_map(|d| {
+        let name: String = d.trim().chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    })
+}
+
+fn package_id_name(id: &str) -> String {
+    let id = id.split('@').next().unwrap();
+    match id.rsplit_once('-') {
+        Some((name, version)) if version.chars().all(|c| c.is_ascii_digit() || c == '.') => name.to_string(),
+        _ => id.to_string(),
+    }
+}
+
+fn cabal_files(dir: &Path) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> =
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "cabal").unwrap_or(false))
+            .collect();
+    out.sort();
+    out
+}
+
+fn load_text(log: &Logger, path: &Path) -> Option<String> {
+    match maybe_read(path) {
+        Ok(Some(b)) => Some(String::from_utf8_lossy(&b).to_string()),
+        Ok(None) => None,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading dep file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn load_yaml(log: &Logger, path: &Path) -> Option<serde_yaml::Value> {
+    match serde_yaml::from_str(&load_text(log, path)?) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!(
+                log,
+                "Error parsing dep file";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn process_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, name: String) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("haskell-hackage-{}", name);
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    let resp =
+                        ctx
+                            .http_get(&format!("https://hackage.haskell.org/package/{}/{}.cabal", name, name))
+                            .await?
+                            .send()
+                            .await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        let fields = parse_cabal(&resp.error_for_status()?.text().await?);
+
+                        // Prefer `source-repository head` over `this`, then the homepage
+                        let mut repos: Vec<&CabalField> =
+                            fields
+                                .iter()
+                                .filter(|f| f.stanza.starts_with("source-repository") && f.name == "location")
+                                .collect();
+                        repos.sort_by_key(|f| !f.stanza.ends_with("head"));
+                        repos
+                            .into_iter()
+                            .chain(fields.iter().filter(|f| f.stanza.is_empty() && f.name == "homepage"))
+                            .map(|f| f.value.clone())
+                            .collect()
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn stack_dep(entry: &serde_yaml::Value, hackage: &mut BTreeSet<String>, git: &mut BTreeSet<String>) {
+    let field = |k: &str| entry.get(k).and_then(|v| v.as_str());
+    if let Some(id) = entry.as_str().or_else(|| field("hackage")) {
+        hackage.insert(package_id_name(id));
+    } else if let Some(url) = field("git") {
+        git.insert(url.to_string());
+    } else if let Some(repo) = field("github") {
+        git.insert(format!("https://github.com/{}", repo));
+    }
+}
+
+pub fn process_haskell(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let mut hackage = BTreeSet::new();
+    let mut git = BTreeSet::new();
+    let mut package_dirs = vec![path.to_path_buf()];
+
+    // Stack
+    let stack_path = path.join("stack.yaml");
+    if let Some(stack) = load_yaml(log, &stack_path) {
+        for p in stack.get("packages").and_then(|p| p.as_sequence()).into_iter().flatten() {
+            if let Some(p) = p.as_str() {
+                package_dirs.push(path.join(p));
+            }
+        }
+        for d in stack.get("extra-deps").and_then(|d| d.as_sequence()).into_iter().flatten() {
+            stack_dep(d, &mut hackage, &mut git);
+        }
+    }
+    if let Some(lock) = load_yaml(log, &path.join("stack.yaml.lock")) {
+        for p in lock.get("packages").and_then(|p| p.as_sequence()).into_iter().flatten() {
+            if let Some(completed) = p.get("completed") {
+                stack_dep(completed, &mut hackage, &mut git);
+            }
+        }
+    }
+
+    // Cabal files
+    let mut local_packages = BTreeSet::new();
+    package_dirs.sort();
+    package_dirs.dedup();
+    for dir in package_dirs {
+        for cabal_path in cabal_files(&dir) {
+            let text = match load_text(log, &cabal_path) {
+                Some(t) => t,
+                None => continue,
+            };
+            for field in parse_cabal(&text) {
+                match field.name.as_str() {
+                    "name" if field.stanza.is_empty() => {
+                        local_packages.insert(field.value);
+                    },
+                    "build-depends" => hackage.extend(build_depends(&field.value)),
+                    _ => { },
+                }
+            }
+        }
+    }
+
+    // The freeze file pins the full dependency tree, like `constraints: any.aeson ==2.1.0.0, any.base installed`
+    if ctx.supercontext.include_indirect {
+        if let Some(freeze) = load_text(log, &path.join("cabal.project.freeze")) {
+            for field in parse_cabal(&freeze) {
+                if field.name != "constraints" {
+                    continue;
+                }
+                for constraint in field.value.split(',') {
+                    let constraint = constraint.trim();
+                    let (name, rest) = constraint.split_once(' ').unwrap_or((constraint, ""));
+
+                    // Flag constraints like `aeson +ordered-keymap` duplicate a version constraint
+                    if rest.starts_with(['+', '-']) {
+                        continue;
+                    }
+                    let name = name.strip_prefix("any.").unwrap_or(name);
+                    if !name.is_empty() {
+                        hackage.insert(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+    for name in hackage {
+        if local_packages.contains(&name) {
+            continue;
+        }
+        process_dep(log, ctx, pool, name);
+    }
+    for url in git {
+        process_git_dep(log, ctx, pool, url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cabal_stanzas_and_continuations() {
+        let text = "\
+name: demo
+-- a comment
+library
+  exposed-modules: Demo
+  build-depends:
+      base >=4 && <5,
+      text
+test-suite spec
+  Build-Depends: hspec, demo:internal
+";
+        let fields: Vec<String> =
+            parse_cabal(text).iter().map(|f| format!("{}|{}|{}", f.stanza, f.name, f.value)).collect();
+        assert_eq!(fields, vec![
+            "|name|demo",
+            "library|exposed-modules|Demo",
+            "library|build-depends| base >=4 && <5, text",
+            "test-suite spec|build-depends|hspec, demo:internal",
+        ]);
+    }
+
+    #[test]
+    fn build_depends_names() {
+        assert_eq!(build_depends("base >=4 && <5, text, mylib:sublib").collect::<Vec<_>>(), vec![
+            "base",
+            "text",
+            "mylib",
+        ]);
+        assert_eq!(package_id_name("acme-missiles-0.3@sha256:abc"), "acme-missiles");
+        assert_eq!(package_id_name("warp-tls"), "warp-tls");
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+    path::Path,
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use structre::structre;
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+#[structre(r#"^\s*"[^"]+":\s*\{:hex,\s*:"?(?P<name>[A-Za-z0-9_]+)"?,"#)]
+struct MixHex {
+    name: String,
+}
+
+#[structre(r#"^\s*"[^"]+":\s*\{:git,\s*"(?P<url>[^"]+)""#)]
+struct MixGit {
+    url: String,
+}
+
+// Text after each `{` in `mix.exs`
+#[structre(r#"^\s*:(?P<name>[a-z0-9_]+)\s*,"#)]
+struct MixExsDep {
+    name: String,
+}
+
+// Text after each `{<<"` in `rebar.lock`
+#[structre(r#"^[^"]+">>,\s*\{pkg,\s*<<"(?P<name>[^"]+)">>[^}]*\},\s*(?P<level>\d+)"#)]
+struct RebarPkg {
+    name: String,
+    level: u32,
+}
+
+#[structre(r#"^[^"]+">>,\s*\{git,\s*"(?P<url>[^"]+)",\s*\{[^}]*\}\},\s*(?P<level>\d+)"#)]
+struct RebarGit {
+    url: String,
+    level: u32,
+}
+
+fn process_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, name: String) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("hex-{}", name);
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    #[derive(Deserialize, Default)]
+                    struct Meta {
+                        #[serde(default)]
+                        links: BTreeMap<String, String>,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Package {
+                        #[serde(default)]
+                        meta: Meta,
+                    }
+
+                    let resp = ctx.http_get(&format!("https://hex.pm/api/packages/{}", name)).await?.send().await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        let package: Package = resp.error_for_status()?.json().await?;
+
+                        // Link names are freeform, but check the usual repo ones first
+                        let (mut repo, other): (Vec<_>, Vec<_>) =
+                            package
+                                .meta
+                                .links
+                                .into_iter()
+                                .partition(
+                                    |(k, _)| ["github", "gitlab", "source", "repository"].contains(
+                                        &k.to_lowercase().as_str(),
+                                    ),
+                                );
+                        repo.extend(other);
+                        repo.into_iter().map(|(_, v)| v).collect()
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn load_text(log: &Logger, path: &Path) -> Option<String> {
+    match maybe_read(path) {
+        Ok(Some(b)) => Some(String::from_utf8_lossy(&b).to_string()),
+        Ok(None) => None,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading dep file";
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+pub fn process_elixir_mix(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("mix.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock = match load_text(&log, &lock_path) {
+        Some(t) => t,
+        None => return,
+    };
+
+    // The lockfile doesn't distinguish direct dependencies, so filter by what's mentioned in `mix.exs`
+    let direct = if ctx.supercontext.include_indirect {
+        None
+    } else {
+        load_text(&log, &path.join("mix.exs")).map(|exs| {
+            let parse = MixExsDepFromRegex::new();
+            let mut names = BTreeSet::new();
+            for part in exs.split('{').skip(1) {
+                if let Ok(d) = parse.parse(part) {
+                    names.insert(d.name);
+                }
+            }
+            names
+        })
+    };
+    let parse_hex = MixHexFromRegex::new();
+    let parse_git = MixGitFromRegex::new();
+    let is_direct = |line: &str| match &direct {
+        Some(direct) => line
+            .trim()
+            .strip_prefix('"')
+            .and_then(|l| l.split_once('"'))
+            .map(|(key, _)| direct.contains(key))
+            .unwrap_or(false),
+        None => true,
+    };
+    for line in lock.lines() {
+        if !is_direct(line) {
+            continue;
+        }
+        if let Ok(hex) = parse_hex.parse(line) {
+            process_dep(&log, ctx, pool, hex.name);
+        } else if let Ok(git) = parse_git.parse(line) {
+            process_git_dep(&log, ctx, pool, git.url);
+        }
+    }
+}
+
+pub fn process_erlang_rebar(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("rebar.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock = match load_text(&log, &lock_path) {
+        Some(t) => t,
+        None => return,
+    };
+    let parse_pkg = RebarPkgFromRegex::new();
+    let parse_git = RebarGitFromRegex::new();
+
+    // Level 0 entries are direct dependencies
+    let max_level = if ctx.supercontext.include_indirect {
+        u32::MAX
+    } else {
+        0
+    };
+    for entry in lock.split("{<<\"").skip(1) {
+        if let Ok(pkg) = parse_pkg.parse(entry) {
+            if pkg.level <= max_level {
+                process_dep(&log, ctx, pool, pkg.name);
+            }
+        } else if let Ok(git) = parse_git.parse(entry) {
+            if git.level <= max_level {
+                process_git_dep(&log, ctx, pool, git.url);
+            }
+        }
+    }
+}
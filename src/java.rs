@@ -1,16 +1,30 @@
 use std::{
-    path::Path,
+    path::{
+        Path,
+        PathBuf,
+    },
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    future::Future,
+    pin::Pin,
+    sync::OnceLock,
 };
 use anyhow::{
+    anyhow,
     Result,
     Context as _,
 };
 use reqwest::StatusCode;
+use serde::Deserialize;
 use slog::{
     Logger,
     warn,
     o,
+    debug,
 };
+use structre::structre;
 use tokio::{
     spawn,
     task::JoinHandle,
@@ -18,9 +32,14 @@ use tokio::{
 use crate::{
     common::{
         maybe_read,
+        home_dir,
+        browsable_url,
+        xml_nodes,
+        xml_text,
         Context,
     },
     aes,
+    es,
 };
 
 fn load_pom(bytes: &[u8]) -> Result<(sxd_document::Package, sxd_xpath::Context<'static>)> {
@@ -31,19 +50,11 @@ fn load_pom(bytes: &[u8]) -> Result<(sxd_document::Package, sxd_xpath::Context<'
     Ok((package, xctx))
 }
 
-fn try_load_pom(path: &Path) -> Result<Option<(sxd_document::Package, sxd_xpath::Context)>> {
-    let bytes = match maybe_read(&path) {
-        Ok(None) => return Ok(None),
-        Err(e) => return Err(e.into()),
-        Ok(Some(r)) => r,
-    };
-    load_pom(&bytes).map(|p| Some(p))
-}
-
-fn process_dep(
+pub fn process_dep(
     log: &Logger,
     ctx: &Context,
     pool: &mut Vec<JoinHandle<()>>,
+    extra_repos: &'static [&'static str],
     dep_group: String,
     dep_name: String,
     dep_ver: String,
@@ -53,51 +64,33 @@ fn process_dep(
     let ctx = ctx.clone();
     pool.push(spawn(async move {
         match aes!({
-            let url =
-                format!(
-                    "https://search.maven.org/remotecontent?filepath={group}/{name}/{ver}/{name}-{ver}.pom",
-                    group = dep_group.replace('.', "/"),
-                    name = dep_name,
-                    ver = dep_ver
-                );
-            let cache_key = format!("java-{}", url);
-            let repo = match ctx.cache_get::<String>(&log, &cache_key).await {
-                Some(r) => r,
-                None => {
-                    fn work_around_rust_lifetime_bugs(resp_bytes: &[u8]) -> Result<String> {
-                        let xpath =
-                            sxd_xpath::Factory::new()
-                                .build("normalize-space(//n:scm/n:url/text())")
-                                .unwrap()
-                                .unwrap();
-                        let (pom, xctx) = load_pom(resp_bytes).context("Error parsing dependency pom.xml")?;
-                        Ok(xpath.evaluate(&xctx, pom.as_document().root()).unwrap().string())
-                    }
-
-                    let resp = ctx.http_get(&url).await?.send().await?;
-                    if resp.status() == StatusCode::NOT_FOUND {
-                        return Ok(());
-                    }
-                    let body = resp.bytes().await?.to_vec();
-                    let repo =
-                        work_around_rust_lifetime_bugs(
-                            &body,
-                        ).with_context(
-                            || format!(
-                                "Failed to extract pom from GET to {} with body:\n[[{}]]",
-                                url,
-                                String::from_utf8_lossy(&body)
-                            ),
-                        )?;
-                    ctx.cache_put(&log, &cache_key, &repo).await;
-                    repo
-                },
+            let pom = match fetch_pom(&log, &ctx, extra_repos, &dep_group, &dep_name, &dep_ver).await? {
+                Some(b) => parse_pom(&b).context("Error parsing dependency pom")?,
+                None => return Ok(()),
             };
-            if !repo.is_empty() {
-                if !ctx.maybe_add_url(&log, &repo).await {
-                    ctx.add_url(&repo).await;
+            // Parents are only needed if the pom's own scm info is missing or refers to their properties
+            let mut lineage = vec![pom.clone()];
+            let mut properties = lineage_properties(&lineage);
+            if pom.scm.is_empty() || pom.scm.iter().any(|u| interpolate(&properties, u).contains("${")) {
+                lineage = load_lineage(&log, &ctx, extra_repos, &pom, None).await;
+                properties = lineage_properties(&lineage);
+            }
+
+            // Closest scm info, then the homepage
+            let scm =
+                lineage
+                    .iter()
+                    .map(|p| p.scm.iter().map(|u| interpolate(&properties, u)).collect::<Vec<_>>())
+                    .find(|u| !u.is_empty())
+                    .unwrap_or_default();
+            for url in scm.iter().chain(lineage.iter().map(|p| &p.url).filter(|u| !u.is_empty()).take(1)) {
+                if ctx.maybe_add_url(&log, url).await {
+                    return Ok(());
                 }
             }
+            if let Some(url) = scm.first() {
+                ctx.add_url(url).await;
+            }
             Ok(())
         }).await {
             Ok(_) => { },
@@ -112,10 +105,509 @@ fn process_dep(
     }));
 }
 
+fn scm_connection_url(connection: &str) -> Option<String> {
+    let rest = connection.strip_prefix("scm:")?;
+    let (_provider, rest) = rest.split_once(':')?;
+    browsable_url(rest)
+}
+
+#[derive(Clone)]
+struct PomDep {
+    group: String,
+    name: String,
+    version: String,
+    type_: String,
+    scope: String,
+}
+
+#[derive(Clone, Default)]
+struct Pom {
+    group: String,
+    name: String,
+    version: String,
+    parent: Option<PomParent>,
+    properties: HashMap<String, String>,
+    managed: Vec<PomDep>,
+    // Including managed ones
+    deps: Vec<PomDep>,
+    modules: Vec<String>,
+    // `url`, `connection` and `developerConnection`
+    scm: Vec<String>,
+    url: String,
+}
+
+#[derive(Clone)]
+struct PomParent {
+    group: String,
+    name: String,
+    version: String,
+    relative_path: String,
+}
+
+fn parse_pom(bytes: &[u8]) -> Result<Pom> {
+    let (package, xctx) = load_pom(bytes)?;
+    let document = package.as_document();
+    let root: sxd_xpath::nodeset::Node = document.root().into();
+    let factory = sxd_xpath::Factory::new();
+    let nodes = |node, xpath| xml_nodes(&factory, &xctx, node, xpath);
+    let text = |node: sxd_xpath::nodeset::Node, xpath: &str| xml_text(&factory, &xctx, node, xpath);
+    let dep = |node: sxd_xpath::nodeset::Node| PomDep {
+        group: text(node, "./n:groupId/text()"),
+        name: text(node, "./n:artifactId/text()"),
+        version: text(node, "./n:version/text()"),
+        type_: text(node, "./n:type/text()"),
+        scope: text(node, "./n:scope/text()"),
+    };
+    let mut out = Pom {
+        group: text(root, "/n:project/n:groupId/text()"),
+        name: text(root, "/n:project/n:artifactId/text()"),
+        version: text(root, "/n:project/n:version/text()"),
+        ..Default::default()
+    };
+    if let Some(parent) = nodes(root, "/n:project/n:parent").into_iter().next() {
+        out.parent = Some(PomParent {
+            group: text(parent, "./n:groupId/text()"),
+            name: text(parent, "./n:artifactId/text()"),
+            version: text(parent, "./n:version/text()"),
+            relative_path: match nodes(parent, "./n:relativePath").is_empty() {
+                true => "../pom.xml".to_string(),
+                false => text(parent, "./n:relativePath/text()"),
+            },
+        });
+    }
+    for property in nodes(root, "/n:project/n:properties/*") {
+        if let Some(name) = property.expanded_name() {
+            out.properties.insert(name.local_part().to_string(), property.string_value().trim().to_string());
+        }
+    }
+    let dep = |node: sxd_xpath::nodeset::Node| {
+        let mut d = dep(node);
+        if d.group.is_empty() && node.expanded_name().map(|n| n.local_part() == "plugin").unwrap_or(false) {
+            d.group = "org.apache.maven.plugins".to_string();
+        }
+        d
+    };
+    for node in nodes(root, "//n:dependencyManagement//n:dependency | //n:pluginManagement//n:plugin") {
+        out.managed.push(dep(node));
+    }
+    for node in nodes(root, "//n:dependency | //n:extension | //n:plugin") {
+        out.deps.push(dep(node));
+    }
+    for (xpath, connection) in [
+        ("/n:project/n:scm/n:url/text()", false),
+        ("/n:project/n:scm/n:connection/text()", true),
+        ("/n:project/n:scm/n:developerConnection/text()", true),
+    ] {
+        let value = text(root, xpath);
+        let url = if connection {
+            scm_connection_url(&value)
+        } else {
+            Some(value)
+        };
+        out.scm.extend(url.filter(|u| !u.is_empty()));
+    }
+    out.url = text(root, "/n:project/n:url/text()");
+    for node in nodes(root, "/n:project/n:modules/n:module") {
+        out.modules.push(node.string_value().trim().to_string());
+    }
+    Ok(out)
+}
+
+const MAVEN_CENTRAL: &str = "https://search.maven.org/remotecontent?filepath=";
+
+struct MavenRemote {
+    url_prefix: String,
+    auth: Option<(String, String)>,
+}
+
+struct MavenSettings {
+    local: Option<PathBuf>,
+    // Central (or its mirror), then repositories from active profiles
+    remotes: Vec<MavenRemote>,
+}
+
+static MAVEN_SETTINGS: OnceLock<MavenSettings> = OnceLock::new();
+
+fn mirror_covers(mirror_of: &str, id: &str) -> bool {
+    let mut covered = false;
+    for pattern in mirror_of.split(',').map(|p| p.trim()) {
+        if pattern.strip_prefix('!') == Some(id) {
+            return false;
+        }
+        if pattern == "*" || pattern == "external:*" || pattern == id {
+            covered = true;
+        }
+    }
+    covered
+}
+
+fn load_maven_settings(log: &Logger) -> MavenSettings {
+    let home = home_dir();
+    let mut out = MavenSettings {
+        local: home.as_ref().map(|h| h.join(".m2/repository")),
+        remotes: vec![],
+    };
+    let settings_path = home.as_ref().map(|h| h.join(".m2/settings.xml"));
+    let settings = match settings_path.as_ref().map(|p| maybe_read(p)).transpose() {
+        Ok(s) => s.flatten(),
+        Err(e) => {
+            warn!(
+                log,
+                "Error reading maven settings";
+                "err" => #? e
+            );
+            None
+        },
+    };
+    let mut remotes = vec![];
+    if let Some(settings) = settings {
+        match es!({
+            let (package, xctx) = load_pom(&settings)?;
+            let document = package.as_document();
+            let root: sxd_xpath::nodeset::Node = document.root().into();
+            let factory = sxd_xpath::Factory::new();
+            let nodes = |node, xpath| xml_nodes(&factory, &xctx, node, xpath);
+            let text = |node: sxd_xpath::nodeset::Node, xpath: &str| xml_text(&factory, &xctx, node, xpath);
+            let local = text(root, "/n:settings/n:localRepository/text()");
+            if !local.is_empty() {
+                let home = home.as_ref().map(|h| h.to_string_lossy().to_string()).unwrap_or_default();
+                out.local = Some(PathBuf::from(local.replace("${user.home}", &home)));
+            }
+            let mut servers = HashMap::new();
+            for server in nodes(root, "/n:settings/n:servers/n:server") {
+                let password = text(server, "./n:password/text()");
+
+                // Encrypted passwords aren't supported
+                if password.starts_with('{') {
+                    continue;
+                }
+                servers.insert(text(server, "./n:id/text()"), (text(server, "./n:username/text()"), password));
+            }
+            let mirrors =
+                nodes(root, "/n:settings/n:mirrors/n:mirror")
+                    .into_iter()
+                    .map(
+                        |m| (text(m, "./n:id/text()"), text(m, "./n:url/text()"), text(m, "./n:mirrorOf/text()")),
+                    )
+                    .collect::<Vec<_>>();
+            let active =
+                nodes(root, "/n:settings/n:activeProfiles/n:activeProfile")
+                    .into_iter()
+                    .map(|n| n.string_value().trim().to_string())
+                    .collect::<Vec<_>>();
+            let mut repos = vec![("central".to_string(), MAVEN_CENTRAL.to_string())];
+            for profile in nodes(root, "/n:settings/n:profiles/n:profile") {
+                if !active.contains(&text(profile, "./n:id/text()")) &&
+                    text(profile, "./n:activation/n:activeByDefault/text()") != "true" {
+                    continue;
+                }
+                for repo in nodes(profile, "./n:repositories/n:repository") {
+                    repos.push((text(repo, "./n:id/text()"), text(repo, "./n:url/text()")));
+                }
+            }
+            for (id, url) in repos {
+                let (id, url) = match mirrors.iter().find(|m| mirror_covers(&m.2, &id)) {
+                    Some(m) => (m.0.clone(), m.1.clone()),
+                    None => (id, url),
+                };
+                remotes.push((id, url));
+            }
+            Ok((remotes, servers))
+        }) {
+            Ok((r, servers)) => {
+                for (id, url) in r {
+                    let url_prefix = if url == MAVEN_CENTRAL {
+                        url
+                    } else {
+                        format!("{}/", url.trim_end_matches('/'))
+                    };
+                    if out.remotes.iter().any(|r| r.url_prefix == url_prefix) {
+                        continue;
+                    }
+                    out.remotes.push(MavenRemote {
+                        url_prefix,
+                        auth: servers.get(&id).cloned(),
+                    });
+                }
+            },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error parsing maven settings";
+                    "err" => #? e
+                );
+            },
+        }
+    }
+    if out.remotes.is_empty() {
+        out.remotes.push(MavenRemote {
+            url_prefix: MAVEN_CENTRAL.to_string(),
+            auth: None,
+        });
+    }
+    out
+}
+
+async fn fetch_pom(
+    log: &Logger,
+    ctx: &Context,
+    extra_repos: &[&str],
+    group: &str,
+    name: &str,
+    version: &str,
+) -> Result<Option<Vec<u8>>> {
+    let settings = MAVEN_SETTINGS.get_or_init(|| load_maven_settings(log));
+    let artifact_path =
+        format!("{group}/{name}/{ver}/{name}-{ver}.pom", group = group.replace('.', "/"), name = name, ver = version);
+    if let Some(local) = &settings.local {
+        if let Some(pom) = maybe_read(&local.join(&artifact_path))? {
+            return Ok(Some(pom));
+        }
+    }
+    let cache_key = format!("java-pom-{}:{}:{}", group, name, version);
+    if let Some(pom) = ctx.cache_get::<Option<String>>(log, &cache_key).await {
+        return Ok(pom.map(|p| p.into_bytes()));
+    }
+    let mut error = None;
+    let mut pom = None;
+    let extra_remotes =
+        extra_repos
+            .iter()
+            .map(|r| MavenRemote {
+                url_prefix: format!("{}/", r.trim_end_matches('/')),
+                auth: None,
+            })
+            .collect::<Vec<_>>();
+    for remote in settings.remotes.iter().chain(extra_remotes.iter()) {
+        let url = format!("{}{}", remote.url_prefix, artifact_path);
+        match aes!({
+            let mut req = ctx.http_get(&url).await?;
+            if let Some((user, password)) = &remote.auth {
+                req = req.basic_auth(user, Some(password));
+            }
+            let resp = req.send().await?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            Ok(Some(String::from_utf8_lossy(&resp.error_for_status()?.bytes().await?).to_string()))
+        }).await {
+            Ok(Some(p)) => {
+                pom = Some(p);
+                break;
+            },
+            Ok(None) => { },
+            Err(e) => {
+                debug!(
+                    log,
+                    "Error fetching pom from repository";
+                    "url" => url,
+                    "err" => #? e
+                );
+                error = Some(e);
+            },
+        }
+    }
+
+    // Don't cache a miss if it might have been due to a transient error
+    if let (None, Some(e)) = (&pom, error) {
+        return Err(e);
+    }
+    ctx.cache_put(log, &cache_key, &pom).await;
+    Ok(pom.map(|p| p.into_bytes()))
+}
+
+fn interpolate(properties: &HashMap<String, String>, value: &str) -> String {
+    let mut value = value.to_string();
+    for _ in 0 .. 10 {
+        let mut out = String::new();
+        let mut rest = value.as_str();
+        let mut changed = false;
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(e) => start + e,
+                None => {
+                    // Unclosed, keep the rest as is
+                    rest = &rest[start..];
+                    break;
+                },
+            };
+            let key = &rest[start + 2 .. end];
+            match properties.get(key) {
+                Some(v) => {
+                    out.push_str(v);
+                    changed = true;
+                },
+                None => out.push_str(&rest[start ..= end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        value = out;
+        if !changed {
+            break;
+        }
+    }
+    value
+}
+
+#[derive(Default)]
+struct EffectivePom {
+    properties: HashMap<String, String>,
+    managed: HashMap<(String, String), String>,
+}
+
+const MAX_POM_DEPTH: usize = 10;
+
+async fn load_parent(
+    log: &Logger,
+    ctx: &Context,
+    extra_repos: &[&str],
+    dir: Option<&Path>,
+    parent: &PomParent,
+) -> Result<Option<(Pom, Option<PathBuf>)>> {
+    if let Some(dir) = dir.filter(|_| !parent.relative_path.is_empty()) {
+        let mut path = dir.join(&parent.relative_path);
+        if path.is_dir() {
+            path = path.join("pom.xml");
+        }
+        if let Some(bytes) = maybe_read(&path)? {
+            let pom = parse_pom(&bytes).with_context(|| format!("Error parsing parent pom at {:?}", path))?;
+            let group = if pom.group.is_empty() {
+                pom.parent.as_ref().map(|p| p.group.clone()).unwrap_or_default()
+            } else {
+                pom.group.clone()
+            };
+            if group == parent.group && pom.name == parent.name {
+                return Ok(Some((pom, path.parent().map(|p| p.to_path_buf()))));
+            }
+        }
+    }
+    match fetch_pom(log, ctx, extra_repos, &parent.group, &parent.name, &parent.version).await? {
+        Some(bytes) => Ok(Some((parse_pom(&bytes).context("Error parsing remote parent pom")?, None))),
+        None => Ok(None),
+    }
+}
+
+async fn load_lineage(
+    log: &Logger,
+    ctx: &Context,
+    extra_repos: &[&str],
+    pom: &Pom,
+    dir: Option<PathBuf>,
+) -> Vec<Pom> {
+    let mut lineage = vec![pom.clone()];
+    let mut dir = dir;
+    while let Some(parent) = lineage.last().unwrap().parent.clone() {
+        if lineage.len() > MAX_POM_DEPTH {
+            warn!(log, "Maximum pom nesting exceeded, ignoring deeper parents");
+            break;
+        }
+        let parent_log = log.new(o!("parent" => format!("{}:{}:{}", parent.group, parent.name, parent.version)));
+        match load_parent(&parent_log, ctx, extra_repos, dir.as_deref(), &parent).await {
+            Ok(Some((p, d))) => {
+                lineage.push(p);
+                dir = d;
+            },
+            Ok(None) => {
+                warn!(parent_log, "Parent pom not found");
+                break;
+            },
+            Err(e) => {
+                warn!(
+                    parent_log,
+                    "Error loading parent pom";
+                    "err" => #? e
+                );
+                break;
+            },
+        }
+    }
+    lineage
+}
+
+fn lineage_properties(lineage: &[Pom]) -> HashMap<String, String> {
+    let mut out = HashMap::new();
+    for p in lineage.iter().rev() {
+        out.extend(p.properties.clone());
+    }
+    let pom = &lineage[0];
+    let group = lineage.iter().map(|p| &p.group).find(|g| !g.is_empty()).cloned().unwrap_or_default();
+    let version = lineage.iter().map(|p| &p.version).find(|v| !v.is_empty()).cloned().unwrap_or_default();
+    for prefix in ["project.", "pom.", ""] {
+        out.insert(format!("{}groupId", prefix), group.clone());
+        out.insert(format!("{}artifactId", prefix), pom.name.clone());
+        out.insert(format!("{}version", prefix), version.clone());
+    }
+    if let Some(parent) = &pom.parent {
+        out.insert("project.parent.groupId".to_string(), parent.group.clone());
+        out.insert("project.parent.artifactId".to_string(), parent.name.clone());
+        out.insert("project.parent.version".to_string(), parent.version.clone());
+    }
+    out
+}
+
+fn effective_pom<'a>(
+    log: &'a Logger,
+    ctx: &'a Context,
+    pom: &'a Pom,
+    dir: Option<PathBuf>,
+    depth: usize,
+) -> Pin<Box<dyn Future<Output = Result<EffectivePom>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut out = EffectivePom::default();
+        if depth > MAX_POM_DEPTH {
+            warn!(log, "Maximum pom nesting exceeded, ignoring deeper parents and imports");
+            return Ok(out);
+        }
+        let lineage = load_lineage(log, ctx, &[], pom, dir).await;
+        out.properties = lineage_properties(&lineage);
+
+        // Managed versions, with descendants overriding ancestors and explicit entries overriding imports
+        let mut imports = vec![];
+        for p in &lineage {
+            for m in &p.managed {
+                let group = interpolate(&out.properties, &m.group);
+                let name = interpolate(&out.properties, &m.name);
+                let version = interpolate(&out.properties, &m.version);
+                if m.scope == "import" && m.type_ == "pom" {
+                    imports.push((group, name, version));
+                    continue;
+                }
+                out.managed.entry((group, name)).or_insert(version);
+            }
+        }
+        for (group, name, version) in imports {
+            let log = log.new(o!("bom" => format!("{}:{}:{}", group, name, version)));
+            let bom = match aes!({
+                let bom = match fetch_pom(&log, ctx, &[], &group, &name, &version).await? {
+                    Some(b) => parse_pom(&b).context("Error parsing imported bom")?,
+                    None => return Err(anyhow!("Imported bom not found")),
+                };
+                effective_pom(&log, ctx, &bom, None, depth + 1).await
+            }).await {
+                Ok(b) => b,
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error loading imported bom";
+                        "err" => #? e
+                    );
+                    continue;
+                },
+            };
+            for (k, v) in bom.managed {
+                out.managed.entry(k).or_insert(v);
+            }
+        }
+        Ok(out)
+    })
+}
+
 pub fn process_java_pom(base_log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, base_path: &Path) {
     let path = base_path.join("pom.xml");
     let log = base_log.new(o!("file" => path.to_string_lossy().to_string()));
-    let (pom, xctx) = match try_load_pom(&path) {
+    let pom = match maybe_read(&path).and_then(|b| b.map(|b| parse_pom(&b)).transpose()) {
         Ok(None) => return,
         Ok(Some(p)) => p,
         Err(e) => {
@@ -127,33 +619,299 @@ pub fn process_java_pom(base_log: &Logger, ctx: &Context, pool: &mut Vec<JoinHan
             return;
         },
     };
-    let factory = sxd_xpath::Factory::new();
-    let xpath_group = factory.build("normalize-space(./n:groupId/text())").unwrap().unwrap();
-    let xpath_name = factory.build("normalize-space(./n:artifactId/text())").unwrap().unwrap();
-    let xpath_ver = factory.build("normalize-space(./n:version/text())").unwrap().unwrap();
-    for xpath in [".//n:dependency", ".//n:extension", ".//n:plugin"] {
-        let xpath_dep = factory.build(xpath).unwrap().unwrap();
-        match xpath_dep.evaluate(&xctx, pom.as_document().root()).unwrap() {
-            sxd_xpath::Value::Nodeset(nodes) => {
-                for node in nodes {
-                    let group = xpath_group.evaluate(&xctx, node).unwrap().string();
-                    let name = xpath_name.evaluate(&xctx, node).unwrap().string();
-                    let ver = xpath_ver.evaluate(&xctx, node).unwrap().string();
-                    process_dep(&log, ctx, pool, group, name, ver);
+    for child in &pom.modules {
+        process_java_pom(base_log, ctx, pool, &base_path.join(child));
+    }
+    let ctx = ctx.clone();
+    let dir = base_path.to_path_buf();
+    pool.push(spawn(async move {
+        let effective = match effective_pom(&log, &ctx, &pom, Some(dir), 0).await {
+            Ok(e) => e,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error resolving parent poms and boms, versions may not be resolved";
+                    "err" => #? e
+                );
+                EffectivePom::default()
+            },
+        };
+        let mut sub_pool = vec![];
+        for d in &pom.deps {
+            let group = interpolate(&effective.properties, &d.group);
+            let name = interpolate(&effective.properties, &d.name);
+            let mut version = interpolate(&effective.properties, &d.version);
+            if version.is_empty() {
+                version = effective.managed.get(&(group.clone(), name.clone())).cloned().unwrap_or_default();
+            }
+            if version.is_empty() || version.contains("${") {
+                debug!(log, "Unable to resolve dependency version"; "dep" => format!("{}:{}", group, name));
+                continue;
+            }
+            process_dep(&log, &ctx, &mut sub_pool, &[], group, name, version);
+        }
+        for f in sub_pool {
+            f.await.unwrap();
+        }
+    }));
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CatalogVersion {
+    Plain(String),
+    Ref {
+        #[serde(rename = "ref")]
+        ref_: String,
+    },
+    Rich {
+        strictly: Option<String>,
+        require: Option<String>,
+        prefer: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CatalogLibrary {
+    // `group:name:version`
+    Notation(String),
+    Detailed {
+        // `group:name`
+        module: Option<String>,
+        group: Option<String>,
+        name: Option<String>,
+        version: Option<CatalogVersion>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Catalog {
+    #[serde(default)]
+    versions: HashMap<String, CatalogVersion>,
+    #[serde(default)]
+    libraries: HashMap<String, CatalogLibrary>,
+}
+
+fn catalog_version(catalog: &Catalog, version: &CatalogVersion) -> Option<String> {
+    match version {
+        CatalogVersion::Plain(v) => Some(v.clone()),
+        CatalogVersion::Ref { ref_ } => match catalog.versions.get(ref_)? {
+            CatalogVersion::Ref { .. } => None,
+            v => catalog_version(catalog, v),
+        },
+        CatalogVersion::Rich { strictly, require, prefer } => strictly.clone().or(require.clone()).or(prefer.clone()),
+    }
+}
+
+fn split_coordinates(coords: &str) -> Option<(String, String, String)> {
+    let mut parts = coords.split(':');
+    let group = parts.next()?;
+    let name = parts.next()?;
+    let version = parts.next()?.split('@').next().unwrap();
+    if group.is_empty() || name.is_empty() || version.is_empty() {
+        return None;
+    }
+    Some((group.to_string(), name.to_string(), version.to_string()))
+}
+
+fn gradle_catalog_deps(log: &Logger, path: &Path, deps: &mut BTreeSet<(String, String, String)>) {
+    let catalog_path = path.join("gradle/libs.versions.toml");
+    let log = log.new(o!("file" => catalog_path.to_string_lossy().to_string()));
+    let catalog = match maybe_read(&catalog_path) {
+        Ok(None) => return,
+        Ok(Some(b)) => match toml::from_slice::<Catalog>(&b) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error parsing version catalog";
+                    "err" => #? e
+                );
+                return;
+            },
+        },
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading version catalog";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    for (key, library) in &catalog.libraries {
+        let dep = match library {
+            CatalogLibrary::Notation(n) => split_coordinates(n),
+            CatalogLibrary::Detailed { module, group, name, version } => {
+                let (group, name) = match (module, group, name) {
+                    (Some(m), _, _) => match m.split_once(':') {
+                        Some((g, n)) => (g.to_string(), n.to_string()),
+                        None => continue,
+                    },
+                    (None, Some(g), Some(n)) => (g.clone(), n.clone()),
+                    _ => continue,
+                };
+                version.as_ref().and_then(|v| catalog_version(&catalog, v)).map(|v| (group, name, v))
+            },
+        };
+        match dep {
+            Some(d) => {
+                deps.insert(d);
+            },
+            None => debug!(log, "Skipping catalog library without resolvable version"; "library" => key),
+        }
+    }
+}
+
+fn gradle_lockfile_deps(log: &Logger, path: &Path, deps: &mut BTreeSet<(String, String, String)>) {
+    for lock_path in [path.join("gradle.lockfile"), path.join("buildscript-gradle.lockfile")] {
+        match maybe_read(&lock_path) {
+            Ok(None) => { },
+            Ok(Some(b)) => {
+                for line in String::from_utf8_lossy(&b).lines() {
+                    if line.starts_with('#') {
+                        continue;
+                    }
+                    deps.extend(split_coordinates(line.split('=').next().unwrap()));
+                }
+            },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading lockfile";
+                    "file" => lock_path.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+            },
+        }
+    }
+}
+
+fn gradle_build_deps(log: &Logger, path: &Path, deps: &mut BTreeSet<(String, String, String)>) {
+    #[structre(r#"^\s*[A-Za-z_]\w*\s*\(?\s*(?:(?:enforcedPlatform|platform)\s*\(\s*)?['"](?P<group>[^'":\s$]+):(?P<name>[^'":\s$]+):(?P<version>[^'":\s$@]+)"#)]
+    struct StringNotation {
+        group: String,
+        name: String,
+        version: String,
+    }
+
+    #[structre(r#"group\s*[:=]\s*['"](?P<group>[^'"$]+)['"]\s*,\s*name\s*[:=]\s*['"](?P<name>[^'"$]+)['"]\s*,\s*version\s*[:=]\s*['"](?P<version>[^'"$]+)['"]"#)]
+    struct MapNotation {
+        group: String,
+        name: String,
+        version: String,
+    }
+
+    let parse_string = StringNotationFromRegex::new();
+    let parse_map = MapNotationFromRegex::new();
+    for build_path in [path.join("build.gradle"), path.join("build.gradle.kts")] {
+        match maybe_read(&build_path) {
+            Ok(None) => { },
+            Ok(Some(b)) => {
+                for line in String::from_utf8_lossy(&b).lines() {
+                    if let Ok(d) = parse_string.parse(line) {
+                        deps.insert((d.group, d.name, d.version));
+                    } else if let Ok(d) = parse_map.parse(line) {
+                        deps.insert((d.group, d.name, d.version));
+                    }
                 }
             },
-            _ => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading build script";
+                    "file" => build_path.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+            },
         }
     }
-    let xpath_modules = factory.build("//n:modules/n:module").unwrap().unwrap();
-    let xpath_text = factory.build("normalize-space(./text())").unwrap().unwrap();
-    match xpath_modules.evaluate(&xctx, pom.as_document().root()).unwrap() {
-        sxd_xpath::Value::Nodeset(nodes) => {
-            for node in nodes {
-                let child = xpath_text.evaluate(&xctx, node).unwrap().string();
-                process_java_pom(base_log, ctx, pool, &base_path.join(child));
+}
+
+fn settings_includes(text: &str) -> Vec<String> {
+    let mut out = vec![];
+    for line in text.lines() {
+        let line = match line.trim().strip_prefix("include") {
+            // Not `includeBuild`, which is a separate build
+            Some(l) if l.starts_with(['(', ' ', '\t']) => l,
+            _ => continue,
+        };
+        for (i, project) in line.split(['"', '\'']).enumerate() {
+            if i % 2 == 1 && !project.is_empty() {
+                out.push(project.trim_start_matches(':').replace(':', "/"));
             }
-        },
-        _ => { },
+        }
+    }
+    out
+}
+
+fn gradle_subprojects(log: &Logger, path: &Path) -> Vec<String> {
+    let mut out = vec![];
+    for settings_path in [path.join("settings.gradle"), path.join("settings.gradle.kts")] {
+        match maybe_read(&settings_path) {
+            Ok(None) => { },
+            Ok(Some(b)) => out.extend(settings_includes(&String::from_utf8_lossy(&b))),
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading settings script";
+                    "file" => settings_path.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+            },
+        }
+    }
+    out
+}
+
+pub fn process_java_gradle(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let mut deps = BTreeSet::new();
+    gradle_catalog_deps(log, path, &mut deps);
+    let mut dirs = vec![path.to_path_buf()];
+    dirs.extend(gradle_subprojects(log, path).into_iter().map(|p| path.join(p)));
+    for dir in dirs {
+        // Lockfiles have the whole resolved graph
+        if ctx.supercontext.include_indirect {
+            gradle_lockfile_deps(log, &dir, &mut deps);
+        }
+        gradle_build_deps(log, &dir, &mut deps);
+    }
+    for (group, name, version) in deps {
+        process_dep(log, ctx, pool, &[], group, name, version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_properties() {
+        let properties = HashMap::from([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "${a}.2".to_string()),
+        ]);
+        assert_eq!(interpolate(&properties, "v${b}-${a}"), "v1.2-1");
+        assert_eq!(interpolate(&properties, "${missing}"), "${missing}");
+        assert_eq!(interpolate(&properties, "a${b"), "a${b");
+        assert_eq!(interpolate(&properties, "${a}${b"), "1${b");
+    }
+
+    #[test]
+    fn interpolate_cycle_terminates() {
+        let properties = HashMap::from([("a".to_string(), "${a}x".to_string())]);
+        assert!(interpolate(&properties, "${a}").starts_with("${a}"));
+    }
+
+    #[test]
+    fn settings_includes_skip_included_builds() {
+        let text = r#"
+rootProject.name = "x"
+include(":app", ":libs:core")
+include 'cli'
+includeBuild("../other")
+"#;
+        assert_eq!(settings_includes(text), vec!["app", "libs/core", "cli"]);
     }
 }
@@ -1,4 +1,18 @@
-use serde::Deserialize;
+use reqwest::{
+    StatusCode,
+    header::{
+        self,
+        HeaderValue,
+    },
+};
+use serde::{
+    Deserialize,
+    Serialize,
+    de::{
+        DeserializeOwned,
+        IgnoredAny,
+    },
+};
 use slog::{
     Logger,
     o,
@@ -15,19 +29,69 @@ use tokio::{
 };
 use std::{
     path::Path,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        BTreeSet,
+    },
 };
 use crate::{
     common::{
         Context,
+        DepRole,
         maybe_read,
     },
     aes,
 };
 
 #[derive(Deserialize)]
-struct PackageRepository {
-    url: String,
+#[serde(untagged)]
+enum PackageRepository {
+    Url(String),
+    Detailed {
+        url: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PackageFundingEntry {
+    Url(String),
+    Detailed {
+        url: String,
+    },
+}
+
+impl PackageFundingEntry {
+    fn url(self) -> String {
+        match self {
+            PackageFundingEntry::Url(u) => u,
+            PackageFundingEntry::Detailed { url } => url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PackageFunding {
+    One(PackageFundingEntry),
+    Many(Vec<PackageFundingEntry>),
+}
+
+impl PackageFunding {
+    fn urls(self) -> Vec<String> {
+        match self {
+            PackageFunding::One(f) => vec![f.url()],
+            PackageFunding::Many(f) => f.into_iter().map(|f| f.url()).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PackageBundle {
+    List(Vec<String>),
+    // `true`, bundling all of `dependencies`
+    All(IgnoredAny),
 }
 
 #[derive(Deserialize)]
@@ -35,7 +99,14 @@ struct Package {
     dependencies: Option<HashMap<String, String>>,
     #[serde(rename = "devDependencies")]
     dev_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "peerDependencies")]
+    peer_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "optionalDependencies")]
+    optional_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "bundleDependencies", alias = "bundledDependencies")]
+    bundle_dependencies: Option<PackageBundle>,
     repository: Option<PackageRepository>,
+    funding: Option<PackageFunding>,
 }
 
 fn try_load_packagejson(path: &Path) -> Result<Option<Package>> {
@@ -46,10 +117,75 @@ fn try_load_packagejson(path: &Path) -> Result<Option<Package>> {
     })?))
 }
 
+fn funding_project_url(funding: &str) -> Option<String> {
+    let url = url::Url::parse(funding).ok()?;
+    if url.host_str()? != "github.com" {
+        return None;
+    }
+    let mut path = url.path_segments()?.filter(|s| !s.is_empty());
+    let owner = path.next()?;
+    // Sponsor and org pages don't say which repo
+    if owner == "sponsors" || owner == "orgs" {
+        return None;
+    }
+    let repo = path.next()?;
+    Some(format!("https://github.com/{}/{}", owner, repo.trim_end_matches(".git")))
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PackageSource {
+    repository: Option<String>,
+    funding: Vec<String>,
+}
+
+impl From<Package> for PackageSource {
+    fn from(p: Package) -> Self {
+        PackageSource {
+            repository: p.repository.map(|r| match r {
+                PackageRepository::Url(u) => u,
+                PackageRepository::Detailed { url } => url,
+            }),
+            funding: p.funding.map(|f| f.urls()).unwrap_or_default(),
+        }
+    }
+}
+
+fn repository_project_url(repo: &str) -> Result<String> {
+    let shorthand = match repo.split_once(':') {
+        Some(("github", r)) => Some(("github.com", r)),
+        Some(("gitlab", r)) => Some(("gitlab.com", r)),
+        Some(("bitbucket", r)) => Some(("bitbucket.org", r)),
+        Some(_) => None,
+        None => Some(("github.com", repo)),
+    };
+    if let Some((host, path)) = shorthand {
+        return Ok(format!("https://{}/{}", host, path.trim_end_matches(".git")));
+    }
+    let url = url::Url::parse(repo).context("Unparsable repo url")?;
+    let path = url.path().rsplitn(2, ".git").collect::<Vec<&str>>().last().unwrap().to_string();
+    Ok(format!("https://{}{}", url.host_str().unwrap_or(""), path))
+}
+
+async fn add_package_source(ctx: &Context, source: PackageSource) -> Result<()> {
+    let project = match &source.repository {
+        Some(repo) => repository_project_url(repo)?,
+        None => match source.funding.iter().find_map(|f| funding_project_url(f)) {
+            Some(p) => p,
+            None => return Ok(()),
+        },
+    };
+    ctx.add_url(&project).await;
+    for f in &source.funding {
+        ctx.add_funding(&project, f).await;
+    }
+    Ok(())
+}
+
 fn process_npm_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, root_path: &Path, dep: &str) {
     let log = log.new(o!("dep" => dep.to_string()));
     let ctx = ctx.clone();
-    let dep_path = root_path.join("node_modules").join(dep).join("package.json");
+    let dep = dep.to_string();
+    let dep_path = root_path.join("node_modules").join(&dep).join("package.json");
     pool.push(spawn(async move {
         match aes!({
             let package = match try_load_packagejson(&dep_path).context("Error loading package.json")? {
@@ -58,13 +194,180 @@ fn process_npm_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>,
                 },
                 Some(p) => p,
             };
-            let repo = match package.repository {
-                None => return Ok(()),
+            add_package_source(&ctx, package.into()).await
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn process_npm_registry_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, dep: String) {
+    let log = log.new(o!("dep" => dep.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("javascript-npm-{}", dep);
+            let source = match ctx.cache_get::<PackageSource>(&log, &cache_key).await {
+                Some(s) => s,
+                None => {
+                    let resp =
+                        ctx
+                            .http_get(&format!("https://registry.npmjs.org/{}/latest", dep))
+                            .await?
+                            .header(header::ACCEPT, HeaderValue::from_static("application/json"))
+                            .send()
+                            .await?;
+                    if resp.status() == StatusCode::NOT_FOUND {
+                        return Err(anyhow!("Package not found in npm registry"));
+                    }
+                    let source: PackageSource = resp.json::<Package>().await?.into();
+                    ctx.cache_put(&log, &cache_key, &source).await;
+                    source
+                },
+            };
+            add_package_source(&ctx, source).await
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn process_jsr_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, dep: String) {
+    let log = log.new(o!("dep" => dep.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let (scope, name) =
+                dep
+                    .strip_prefix('@')
+                    .and_then(|d| d.split_once('/'))
+                    .ok_or_else(|| anyhow!("JSR package name isn't scoped"))?;
+            let cache_key = format!("javascript-jsr-{}", dep);
+            let repo = match ctx.cache_get::<Option<String>>(&log, &cache_key).await {
                 Some(r) => r,
+                None => {
+                    #[derive(Deserialize)]
+                    struct JsrRepository {
+                        owner: String,
+                        name: String,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct JsrPackage {
+                        #[serde(rename = "githubRepository")]
+                        github_repository: Option<JsrRepository>,
+                    }
+
+                    let resp: JsrPackage =
+                        ctx
+                            .http_get(&format!("https://api.jsr.io/scopes/{}/packages/{}", scope, name))
+                            .await?
+                            .header(header::ACCEPT, HeaderValue::from_static("application/json"))
+                            .send()
+                            .await?
+                            .json()
+                            .await?;
+                    let repo = resp.github_repository.map(|r| format!("https://github.com/{}/{}", r.owner, r.name));
+                    ctx.cache_put(&log, &cache_key, &repo).await;
+                    repo
+                },
+            };
+            if let Some(repo) = repo {
+                ctx.add_url(&repo).await;
+            }
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn process_deno_x_dep(
+    log: &Logger,
+    ctx: &Context,
+    pool: &mut Vec<JoinHandle<()>>,
+    module: String,
+    version: Option<String>,
+) {
+    let log = log.new(o!("dep" => module.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("javascript-deno-x-{}", module);
+            let repo = match ctx.cache_get::<Option<String>>(&log, &cache_key).await {
+                Some(r) => r,
+                None => {
+                    #[derive(Deserialize)]
+                    struct Versions {
+                        latest: String,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct UploadOptions {
+                        #[serde(rename = "type")]
+                        type_: String,
+                        repository: String,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Meta {
+                        upload_options: UploadOptions,
+                    }
+
+                    let version = match &version {
+                        Some(v) => v.clone(),
+                        None => {
+                            ctx
+                                .http_get(&format!("https://cdn.deno.land/{}/meta/versions.json", module))
+                                .await?
+                                .send()
+                                .await?
+                                .json::<Versions>()
+                                .await?
+                                .latest
+                        },
+                    };
+                    let resp: Meta =
+                        ctx
+                            .http_get(&format!("https://cdn.deno.land/{}/versions/{}/meta/meta.json", module, version))
+                            .await?
+                            .send()
+                            .await?
+                            .json()
+                            .await?;
+                    let repo = if resp.upload_options.type_ == "github" {
+                        Some(format!("https://github.com/{}", resp.upload_options.repository))
+                    } else {
+                        None
+                    };
+                    ctx.cache_put(&log, &cache_key, &repo).await;
+                    repo
+                },
             };
-            let url = url::Url::parse(&repo.url).context("Unparsable repo url")?;
-            let path = url.path().rsplitn(2, ".git").collect::<Vec<&str>>().last().unwrap().to_string();
-            ctx.add_url(&format!("https://{}{}", url.host_str().unwrap_or(""), path)).await;
+            if let Some(repo) = repo {
+                ctx.add_url(&repo).await;
+            }
             Ok(())
         }).await {
             Ok(_) => { },
@@ -79,6 +382,348 @@ fn process_npm_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>,
     }));
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum JsDep {
+    Npm(String),
+    Jsr(String),
+    DenoX(String, Option<String>),
+    Url(String),
+}
+
+fn process_js_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, dep: JsDep) {
+    match dep {
+        JsDep::Npm(d) => process_npm_registry_dep(log, ctx, pool, d),
+        JsDep::Jsr(d) => process_jsr_dep(log, ctx, pool, d),
+        JsDep::DenoX(m, v) => process_deno_x_dep(log, ctx, pool, m, v),
+        JsDep::Url(u) => {
+            let log = log.new(o!("dep" => u.clone()));
+            let ctx = ctx.clone();
+            pool.push(spawn(async move {
+                if !ctx.maybe_add_url(&log, &u).await {
+                    warn!(log, "Unrecognized dependency url");
+                }
+            }));
+        },
+    }
+}
+
+fn split_package_spec(spec: &str) -> (String, Option<String>) {
+    let spec = spec.trim_start_matches('/');
+    let segments = if spec.starts_with('@') {
+        2
+    } else {
+        1
+    };
+    let id = spec.splitn(segments + 1, '/').take(segments).collect::<Vec<_>>().join("/");
+    match id.get(1..).and_then(|i| i.find('@')) {
+        Some(i) => (id[..i + 1].to_string(), Some(id[i + 2..].to_string())),
+        None => (id, None),
+    }
+}
+
+fn parse_deno_specifier(spec: &str) -> Option<JsDep> {
+    if let Some(s) = spec.strip_prefix("npm:") {
+        return Some(JsDep::Npm(split_package_spec(s).0));
+    }
+    if let Some(s) = spec.strip_prefix("jsr:") {
+        return Some(JsDep::Jsr(split_package_spec(s).0));
+    }
+    let url = url::Url::parse(spec).ok()?;
+    if !["http", "https"].contains(&url.scheme()) {
+        return None;
+    }
+    let path = url.path();
+    match url.host_str()? {
+        "deno.land" => {
+            if let Some(p) = path.strip_prefix("/x/") {
+                let (module, version) = split_package_spec(p);
+                return Some(JsDep::DenoX(module, version));
+            }
+            if path.starts_with("/std") {
+                return Some(JsDep::Url("https://github.com/denoland/deno_std".to_string()));
+            }
+            None
+        },
+        "jsr.io" => Some(JsDep::Jsr(split_package_spec(path).0)),
+        "esm.sh" | "unpkg.com" | "cdn.skypack.dev" => Some(JsDep::Npm(split_package_spec(path).0)),
+        "cdn.jsdelivr.net" => Some(JsDep::Npm(split_package_spec(path.strip_prefix("/npm/")?).0)),
+        "raw.githubusercontent.com" => {
+            let mut segs = url.path_segments()?;
+            Some(JsDep::Url(format!("https://github.com/{}/{}", segs.next()?, segs.next()?)))
+        },
+        _ => Some(JsDep::Url(spec.to_string())),
+    }
+}
+
+fn next_jsonc_token(mut chars: std::iter::Peekable<std::str::Chars>) -> Option<char> {
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => { },
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            },
+            c => return Some(c),
+        }
+    }
+    None
+}
+
+/// Strip comments and trailing commas so JSONC files (`deno.jsonc`, `bun.lock`) can be read as JSON.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        out.push(c);
+                    }
+                },
+                '"' => in_string = false,
+                _ => { },
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push(c);
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            },
+            ',' => {
+                if !matches!(next_jsonc_token(chars.clone()), Some('}') | Some(']')) {
+                    out.push(c);
+                }
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn try_load_jsonc<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let bytes = match maybe_read(path)? {
+        None => return Ok(None),
+        Some(b) => b,
+    };
+    Ok(Some(serde_json::from_str(&strip_jsonc(&String::from_utf8_lossy(&bytes)))?))
+}
+
+fn deno_lock_deps(lock: &serde_json::Value, include_indirect: bool) -> BTreeSet<JsDep> {
+    fn keys(v: Option<&serde_json::Value>) -> impl Iterator<Item = &String> {
+        v.and_then(|v| v.as_object()).into_iter().flat_map(|o| o.keys())
+    }
+
+    let version = lock.get("version").and_then(|v| v.as_str()).map(|v| v.parse::<u32>().unwrap_or(0));
+
+    // Version 1 is only the remote module hashes; version 2 has npm specifiers without the `npm:` prefix
+    let (specifiers, npm, jsr, remote) = match version {
+        None => (None, None, None, Some(lock)),
+        Some(..= 2) => (lock.pointer("/npm/specifiers"), lock.pointer("/npm/packages"), None, lock.get("remote")),
+        Some(3) => (
+            lock.pointer("/packages/specifiers"),
+            lock.pointer("/packages/npm"),
+            lock.pointer("/packages/jsr"),
+            lock.get("remote"),
+        ),
+        Some(_) => (lock.get("specifiers"), lock.get("npm"), lock.get("jsr"), lock.get("remote")),
+    };
+    let mut out = BTreeSet::new();
+    if include_indirect {
+        out.extend(keys(npm).map(|k| JsDep::Npm(split_package_spec(k).0)));
+        out.extend(keys(jsr).map(|k| JsDep::Jsr(split_package_spec(k).0)));
+        out.extend(keys(remote).filter_map(|s| parse_deno_specifier(s)));
+        return out;
+    }
+
+    // Version 3 and later list what the workspace (and its members) import, otherwise fall back to every specifier
+    let workspace = lock.get("workspace");
+    let members =
+        workspace.and_then(|w| w.get("members")).and_then(|m| m.as_object()).into_iter().flat_map(|m| m.values());
+    let workspace_deps: Vec<&str> =
+        workspace
+            .into_iter()
+            .chain(members)
+            .flat_map(|w| [w.get("dependencies"), w.pointer("/packageJson/dependencies")])
+            .flatten()
+            .filter_map(|d| d.as_array())
+            .flatten()
+            .filter_map(|d| d.as_str())
+            .collect();
+    if !workspace_deps.is_empty() {
+        out.extend(workspace_deps.into_iter().filter_map(parse_deno_specifier));
+    } else if version.unwrap_or(0) <= 2 {
+        out.extend(keys(specifiers).map(|k| JsDep::Npm(split_package_spec(k).0)));
+    } else {
+        out.extend(keys(specifiers).filter_map(|s| parse_deno_specifier(s)));
+    }
+    out
+}
+
+pub fn process_javascript_deno(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    #[derive(Deserialize)]
+    struct ImportMap {
+        #[serde(default)]
+        imports: HashMap<String, String>,
+        #[serde(rename = "importMap")]
+        import_map: Option<String>,
+    }
+
+    let mut deps = BTreeSet::new();
+    for config_path in [path.join("deno.json"), path.join("deno.jsonc")] {
+        let log = log.new(o!("file" => config_path.to_string_lossy().to_string()));
+        let config = match try_load_jsonc::<ImportMap>(&config_path) {
+            Ok(None) => continue,
+            Ok(Some(c)) => c,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading deno config";
+                    "err" => #? e
+                );
+                continue;
+            },
+        };
+        let mut imports = config.imports;
+        if let Some(import_map_path) = config.import_map {
+            match try_load_jsonc::<ImportMap>(&path.join(import_map_path)) {
+                Ok(None) => warn!(log, "Import map referenced in deno config is missing"),
+                Ok(Some(m)) => imports.extend(m.imports),
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error loading import map";
+                        "err" => #? e
+                    );
+                },
+            }
+        }
+        deps.extend(imports.values().filter_map(|s| parse_deno_specifier(s)));
+    }
+
+    let lock_path = path.join("deno.lock");
+    match try_load_jsonc::<serde_json::Value>(&lock_path) {
+        Ok(None) => { },
+        Ok(Some(lock)) => deps.extend(deno_lock_deps(&lock, ctx.supercontext.include_indirect)),
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading deno lockfile";
+                "file" => lock_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+        },
+    }
+    let ctx = ctx.with_role(DepRole::Runtime);
+    for dep in deps {
+        process_js_dep(log, &ctx, pool, dep);
+    }
+}
+
+pub fn process_javascript_bun(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("bun.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+
+    #[derive(Deserialize)]
+    struct Workspace {
+        dependencies: Option<HashMap<String, String>>,
+        #[serde(rename = "devDependencies")]
+        dev_dependencies: Option<HashMap<String, String>>,
+        #[serde(rename = "peerDependencies")]
+        peer_dependencies: Option<HashMap<String, String>>,
+        #[serde(rename = "optionalDependencies")]
+        optional_dependencies: Option<HashMap<String, String>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Lock {
+        #[serde(default)]
+        workspaces: HashMap<String, Workspace>,
+        // Entries start with the resolution, like `name@1.2.3` or `name@github:owner/repo#ref`
+        #[serde(default)]
+        packages: HashMap<String, Vec<serde_json::Value>>,
+    }
+
+    let lock = match try_load_jsonc::<Lock>(&lock_path) {
+        Ok(None) => return,
+        Ok(Some(l)) => l,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading bun lockfile";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let mut deps = BTreeSet::new();
+    for workspace in lock.workspaces.into_values() {
+        for (role, m) in [
+            (DepRole::Runtime, workspace.dependencies),
+            (DepRole::Dev, workspace.dev_dependencies),
+            (DepRole::Peer, workspace.peer_dependencies),
+            (DepRole::Optional, workspace.optional_dependencies),
+        ] {
+            for dep in m.into_iter().flat_map(|m| m.into_keys()) {
+                let resolution = match lock.packages.get(&dep).and_then(|p| p.first()).and_then(|r| r.as_str()) {
+                    Some(r) => r,
+                    None => {
+                        warn!(log, "Dependency missing from lockfile packages"; "dep" => dep);
+                        continue;
+                    },
+                };
+                let (name, source) = match resolution.get(1..).and_then(|r| r.find('@')) {
+                    Some(i) => (resolution[..i + 1].to_string(), &resolution[i + 2..]),
+                    None => (resolution.to_string(), ""),
+                };
+                let dep = if let Some(s) = source.strip_prefix("github:") {
+                    JsDep::Url(format!("https://github.com/{}", s.split('#').next().unwrap()))
+                } else if let Some(s) = source.strip_prefix("git+") {
+                    JsDep::Url(s.split('#').next().unwrap().to_string())
+                } else if ["workspace:", "file:", "link:"].iter().any(|p| source.starts_with(p)) {
+                    continue;
+                } else {
+                    JsDep::Npm(name)
+                };
+                deps.insert((dep, role));
+            }
+        }
+    }
+    for (dep, role) in deps {
+        process_js_dep(&log, &ctx.with_role(role), pool, dep);
+    }
+}
+
 pub fn process_javascript_npm(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
     let package_path = path.join("package.json");
     let log = log.new(o!("file" => package_path.to_string_lossy().to_string()));
@@ -94,10 +739,110 @@ pub fn process_javascript_npm(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHa
         Ok(None) => return,
         Ok(Some(p)) => p,
     };
-    for dep in package.dependencies.iter().map(|m| m.keys()).flatten() {
-        process_npm_dep(&log, ctx, pool, path, dep);
+    let mut deps = BTreeSet::new();
+    for (role, m) in [
+        (DepRole::Runtime, package.dependencies),
+        (DepRole::Dev, package.dev_dependencies),
+        (DepRole::Peer, package.peer_dependencies),
+        (DepRole::Optional, package.optional_dependencies),
+    ] {
+        for dep in m.into_iter().flat_map(|m| m.into_keys()) {
+            deps.insert((dep, role));
+        }
+    }
+    if let Some(PackageBundle::List(bundled)) = package.bundle_dependencies {
+        for dep in bundled {
+            deps.insert((dep, DepRole::Runtime));
+        }
+    }
+    for (dep, role) in deps {
+        process_npm_dep(&log, &ctx.with_role(role), pool, path, &dep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_package_spec_versions() {
+        assert_eq!(split_package_spec("chalk@5.3.0"), ("chalk".to_string(), Some("5.3.0".to_string())));
+        assert_eq!(split_package_spec("@std/path@^1.0.0"), ("@std/path".to_string(), Some("^1.0.0".to_string())));
+        assert_eq!(split_package_spec("/@std/path@1.0.0/mod.ts").0, "@std/path");
+        assert_eq!(split_package_spec("chalk"), ("chalk".to_string(), None));
+    }
+
+    #[test]
+    fn strip_jsonc_comments_and_trailing_commas() {
+        let text = r#"{
+            // comment
+            "a": "http://x", /* block */
+            "b": [1, 2,],
+            "c": 3, // trailing
+            /* last */
+        }"#;
+        let v: serde_json::Value = serde_json::from_str(&strip_jsonc(text)).unwrap();
+        assert_eq!(v, serde_json::json!({
+            "a": "http://x",
+            "b": [1, 2],
+            "c": 3
+        }));
+    }
+
+    #[test]
+    fn strip_jsonc_keeps_string_contents() {
+        assert_eq!(strip_jsonc(r#"{"a": "// not, a comment,]"}"#), r#"{"a": "// not, a comment,]"}"#);
     }
-    for dep in package.dev_dependencies.iter().map(|m| m.keys()).flatten() {
-        process_npm_dep(&log, ctx, pool, path, dep);
+
+    #[test]
+    fn deno_lock_v2() {
+        let lock = serde_json::json!({
+            "version": "2",
+            "remote": { "https://deno.land/x/oak@v12.6.1/mod.ts": "abc" },
+            "npm": {
+                "specifiers": { "chalk@5": "chalk@5.3.0" },
+                "packages": { "chalk@5.3.0": { }, "ansi-styles@6.2.1": { } }
+            }
+        });
+        assert_eq!(deno_lock_deps(&lock, false), BTreeSet::from([JsDep::Npm("chalk".to_string())]));
+        assert_eq!(deno_lock_deps(&lock, true), BTreeSet::from([
+            JsDep::Npm("chalk".to_string()),
+            JsDep::Npm("ansi-styles".to_string()),
+            JsDep::DenoX("oak".to_string(), Some("v12.6.1".to_string())),
+        ]));
+    }
+
+    #[test]
+    fn deno_lock_v3() {
+        let lock = serde_json::json!({
+            "version": "3",
+            "packages": {
+                "specifiers": { "jsr:@std/path@1": "jsr:@std/path@1.0.0", "npm:chalk@5": "npm:chalk@5.3.0" },
+                "jsr": { "@std/path@1.0.0": { } },
+                "npm": { "chalk@5.3.0": { } }
+            },
+            "remote": { },
+            "workspace": { "dependencies": ["jsr:@std/path@1"] }
+        });
+        assert_eq!(deno_lock_deps(&lock, false), BTreeSet::from([JsDep::Jsr("@std/path".to_string())]));
+        assert_eq!(deno_lock_deps(&lock, true), BTreeSet::from([
+            JsDep::Jsr("@std/path".to_string()),
+            JsDep::Npm("chalk".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn deno_lock_v4() {
+        let lock = serde_json::json!({
+            "version": "4",
+            "specifiers": { "jsr:@std/path@1": "1.0.0", "npm:chalk@5": "5.3.0" },
+            "jsr": { "@std/path@1.0.0": { } },
+            "npm": { "chalk@5.3.0": { }, "ansi-styles@6.2.1": { } }
+        });
+        assert_eq!(deno_lock_deps(&lock, false), BTreeSet::from([
+            JsDep::Jsr("@std/path".to_string()),
+            JsDep::Npm("chalk".to_string()),
+        ]));
+        assert_eq!(deno_lock_deps(&lock, true).len(), 3);
     }
 }
@@ -0,0 +1,330 @@
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    env,
+    fs,
+    io::Read,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use anyhow::{
+    Result,
+    anyhow,
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::task::JoinHandle;
+use crate::common::{
+    Context,
+    home_dir,
+    maybe_read,
+    process_git_dep,
+    version_key,
+};
+
+fn load_toml(log: &Logger, path: &Path) -> Option<toml::Value> {
+    let res = match maybe_read(path) {
+        Ok(Some(b)) => toml::from_slice(&b).map_err(|e| e.into()),
+        Ok(None) => return None,
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading toml";
+                "file" => path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    }
+}
+
+fn depots() -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = match env::var_os("JULIA_DEPOT_PATH") {
+        Some(p) => env::split_paths(&p).filter(|p| !p.as_os_str().is_empty()).collect(),
+        None => vec![],
+    };
+    if out.is_empty() {
+        out.extend(home_dir().map(|h| h.join(".julia")));
+    }
+    out
+}
+
+enum PackageToml {
+    File(PathBuf),
+    Packed(Vec<u8>),
+}
+
+impl PackageToml {
+    fn load(&self, log: &Logger) -> Option<toml::Value> {
+        match self {
+            PackageToml::File(p) => load_toml(log, p),
+            PackageToml::Packed(b) => match toml::from_slice(b) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error parsing packed Package.toml";
+                        "err" => #? e
+                    );
+                    None
+                },
+            },
+        }
+    }
+}
+
+fn read_packed_registry(tarball: impl Read) -> Result<(toml::Value, HashMap<String, Vec<u8>>)> {
+    let mut registry = None;
+    let mut packages = HashMap::new();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+        if path == "Registry.toml" {
+            let mut bytes = vec![];
+            entry.read_to_end(&mut bytes)?;
+            registry = Some(toml::from_slice(&bytes)?);
+        } else if path.ends_with("/Package.toml") {
+            let mut bytes = vec![];
+            entry.read_to_end(&mut bytes)?;
+            packages.insert(path, bytes);
+        }
+    }
+    Ok((registry.ok_or_else(|| anyhow!("Registry tarball has no Registry.toml"))?, packages))
+}
+
+fn load_registries(log: &Logger) -> HashMap<String, PackageToml> {
+    let mut out = HashMap::new();
+    for depot in depots() {
+        let registries_dir = depot.join("registries");
+        for e in fs::read_dir(&registries_dir).into_iter().flatten().flatten() {
+            let registry_path = e.path();
+            let (registry, mut packed) = if registry_path.is_dir() {
+                match load_toml(log, &registry_path.join("Registry.toml")) {
+                    Some(r) => (r, None),
+                    None => continue,
+                }
+            } else if registry_path.extension().map(|e| e == "toml").unwrap_or(false) {
+                // Since Julia 1.7 registries are a `<name>.toml` pointing at a tarball
+                let tarball =
+                    match load_toml(log, &registry_path).and_then(|r| r.get("path")?.as_str().map(|p| p.to_string())) {
+                        Some(p) => registries_dir.join(p),
+                        None => continue,
+                    };
+                match fs::File::open(&tarball).map_err(|e| e.into()).and_then(read_packed_registry) {
+                    Ok((r, packages)) => (r, Some(packages)),
+                    Err(e) => {
+                        warn!(
+                            log,
+                            "Error reading compressed registry";
+                            "file" => tarball.to_string_lossy().to_string(),
+                            "err" => #? e
+                        );
+                        continue;
+                    },
+                }
+            } else {
+                continue;
+            };
+            for (uuid, package) in registry.get("packages").and_then(|p| p.as_table()).into_iter().flatten() {
+                let path = match package.get("path").and_then(|p| p.as_str()) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                let package_toml = match &mut packed {
+                    Some(packed) => match packed.remove(&format!("{}/Package.toml", path)) {
+                        Some(b) => PackageToml::Packed(b),
+                        None => continue,
+                    },
+                    None => PackageToml::File(registry_path.join(path).join("Package.toml")),
+                };
+                out.entry(uuid.clone()).or_insert(package_toml);
+            }
+        }
+    }
+    out
+}
+
+fn manifest_path(path: &Path) -> Option<PathBuf> {
+    let mut versioned: Vec<(String, PathBuf)> =
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let version =
+                    name
+                        .strip_prefix("JuliaManifest-v")
+                        .or_else(|| name.strip_prefix("Manifest-v"))?
+                        .strip_suffix(".toml")?
+                        .to_string();
+                Some((version, e.path()))
+            })
+            .collect();
+    versioned.sort_by_cached_key(|(v, p)| (version_key(v), p.file_name().unwrap().len()));
+    if let Some((_, p)) = versioned.pop() {
+        return Some(p);
+    }
+    ["JuliaManifest.toml", "Manifest.toml"].iter().map(|n| path.join(n)).find(|p| p.exists())
+}
+
+fn manifest_entries(manifest: &toml::Value) -> Vec<(&str, &toml::Value)> {
+    // Format 1 has no `manifest_format` and no `[deps]` table
+    let table = match manifest.get("manifest_format") {
+        Some(_) => manifest.get("deps"),
+        None => Some(manifest),
+    };
+    let mut out = vec![];
+    for (name, entries) in table.and_then(|t| t.as_table()).into_iter().flatten() {
+        for entry in entries.as_array().into_iter().flatten() {
+            out.push((name.as_str(), entry));
+        }
+    }
+    out
+}
+
+pub fn process_julia(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let manifest_path = match manifest_path(path) {
+        Some(p) => p,
+        None => return,
+    };
+    let log = log.new(o!("file" => manifest_path.to_string_lossy().to_string()));
+    let manifest = match load_toml(&log, &manifest_path) {
+        Some(m) => m,
+        None => return,
+    };
+
+    // Direct dependencies by uuid, from the project file
+    let direct: Option<BTreeSet<String>> = if ctx.supercontext.include_indirect {
+        None
+    } else {
+        ["JuliaProject.toml", "Project.toml"]
+            .iter()
+            .map(|n| path.join(n))
+            .find(|p| p.exists())
+            .and_then(|p| load_toml(&log, &p))
+            .map(
+                |project| project
+                    .get("deps")
+                    .and_then(|d| d.as_table())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(_, uuid)| uuid.as_str().map(|u| u.to_string()))
+                    .collect(),
+            )
+    };
+    let registries = load_registries(&log);
+    for (name, entry) in manifest_entries(&manifest) {
+        let field = |k: &str| entry.get(k).and_then(|v| v.as_str());
+        let uuid = match field("uuid") {
+            Some(u) => u,
+            None => continue,
+        };
+        if let Some(direct) = &direct {
+            if !direct.contains(uuid) {
+                continue;
+            }
+        }
+
+        // Packages tracking a repo directly
+        if let Some(url) = field("repo-url") {
+            process_git_dep(&log, ctx, pool, url.to_string());
+            continue;
+        }
+
+        // Stdlibs have no tree hash, and developed packages have a local path
+        if field("git-tree-sha1").is_none() || field("path").is_some() {
+            continue;
+        }
+        let package_toml = match registries.get(uuid) {
+            Some(p) => p,
+            None => {
+                warn!(log, "Package not found in any local registry"; "dep" => name);
+                continue;
+            },
+        };
+        match package_toml.load(&log).and_then(|p| p.get("repo").and_then(|r| r.as_str()).map(|r| r.to_string())) {
+            Some(repo) => process_git_dep(&log, ctx, pool, repo),
+            None => warn!(log, "Registry package has no repo"; "dep" => name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_entries_formats() {
+        let format1: toml::Value = toml::from_str(r#"
+[[Example]]
+git-tree-sha1 = "abc"
+uuid = "7876af07-990d-54b4-ab0e-23690620f79a"
+version = "0.5.3"
+
+[[Random]]
+uuid = "9a3f8284-a2c9-5f02-9a11-845980a1fd5c"
+"#).unwrap();
+        let format2: toml::Value = toml::from_str(r#"
+julia_version = "1.10.0"
+manifest_format = "2.0"
+
+[[deps.Example]]
+git-tree-sha1 = "abc"
+uuid = "7876af07-990d-54b4-ab0e-23690620f79a"
+version = "0.5.3"
+
+[[deps.Random]]
+uuid = "9a3f8284-a2c9-5f02-9a11-845980a1fd5c"
+"#).unwrap();
+        for manifest in [&format1, &format2] {
+            let names: Vec<&str> = manifest_entries(manifest).into_iter().map(|(n, _)| n).collect();
+            assert_eq!(names, vec!["Example", "Random"]);
+        }
+    }
+
+    #[test]
+    fn packed_registry() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(vec![], flate2::Compression::default()));
+        let registry = r#"
+[packages]
+7876af07-990d-54b4-ab0e-23690620f79a = { name = "Example", path = "E/Example" }
+"#;
+        for (path, text) in [
+            ("Registry.toml", registry),
+            ("E/Example/Package.toml", "name = \"Example\"\nrepo = \"https://github.com/JuliaLang/Example.jl.git\"\n"),
+            ("E/Example/Versions.toml", ""),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, path, text.as_bytes()).unwrap();
+        }
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+        let (registry, packages) = read_packed_registry(tarball.as_slice()).unwrap();
+        assert!(registry.get("packages").unwrap().get("7876af07-990d-54b4-ab0e-23690620f79a").is_some());
+        assert_eq!(packages.keys().collect::<Vec<_>>(), vec!["E/Example/Package.toml"]);
+    }
+
+    #[test]
+    fn versioned_manifest_preferred() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(manifest_path(dir.path()), None);
+        for name in ["Manifest.toml", "Manifest-v1.10.toml", "Manifest-v1.9.toml"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        assert_eq!(manifest_path(dir.path()), Some(dir.path().join("Manifest-v1.10.toml")));
+    }
+}
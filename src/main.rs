@@ -22,8 +22,15 @@ use common::{
     USER_AGENT,
     WorkingAccount,
 };
-use java::process_java_pom;
-use javascript::process_javascript_npm;
+use java::{
+    process_java_pom,
+    process_java_gradle,
+};
+use javascript::{
+    process_javascript_npm,
+    process_javascript_deno,
+    process_javascript_bun,
+};
 use path_absolutize::Absolutize;
 use platform_dirs::AppDirs;
 use python::process_python_pyproject;
@@ -63,6 +70,35 @@ use std::{
 use crate::{
     golang::process_golang_gomod,
     rust::process_rust_cargo,
+    scala::process_scala_sbt,
+    clojure::{
+        process_clojure_lein,
+        process_clojure_deps,
+    },
+    ruby::process_ruby_bundler,
+    php::process_php_composer,
+    dotnet::process_dotnet_nuget,
+    hex::{
+        process_elixir_mix,
+        process_erlang_rebar,
+    },
+    dart::process_dart_pub,
+    swift::{
+        process_swift_pm,
+        process_swift_cocoapods,
+    },
+    haskell::process_haskell,
+    r::process_r,
+    julia::process_julia,
+    nix::process_nix_flake,
+    ocaml::process_ocaml_opam,
+    zig::process_zig,
+    cpp::{
+        process_cpp_vcpkg,
+        process_cpp_conan,
+    },
+    git::process_git_submodules,
+    ci::process_ci_tooling,
     common::Supercontext,
 };
 
@@ -73,8 +109,26 @@ pub mod javascript;
 pub mod java;
 pub mod python;
 pub mod rust;
+pub mod scala;
+pub mod clojure;
+pub mod ruby;
+pub mod php;
+pub mod dotnet;
+pub mod hex;
+pub mod dart;
+pub mod swift;
+pub mod haskell;
+pub mod r;
+pub mod julia;
+pub mod nix;
+pub mod ocaml;
+pub mod zig;
+pub mod cpp;
+pub mod git;
+pub mod ci;
 pub mod os_arch;
 pub mod os_debian;
+pub mod os_nixos;
 
 pub const ENV_BREAD_TOKEN: &'static str = "BREAD_TOKEN";
 
@@ -82,6 +136,7 @@ pub const ENV_BREAD_TOKEN: &'static str = "BREAD_TOKEN";
 pub enum Os {
     Debian,
     Arch,
+    Nixos,
 }
 
 #[derive(Clone, Debug)]
@@ -107,6 +162,7 @@ impl FromStr for ArgSource {
                 match splits.next().ok_or_else(|| anyhow!("Missing OS name"))? {
                     "debian" => Ok(ArgSource::Os(Os::Debian)),
                     "arch" => Ok(ArgSource::Os(Os::Arch)),
+                    "nixos" => Ok(ArgSource::Os(Os::Nixos)),
                     o => Err(anyhow!("Unrecognized os [[{}]]", o)),
                 }
             },
@@ -188,6 +244,11 @@ pub struct Args {
     remove: bool,
     #[arg(long, help = "Delete account entries at the destination if they weren't present in the scan results")]
     remove_accounts: bool,
+    #[arg(
+        long,
+        help = "Also include indirect (transitive) dependencies for scanners that support it, see the readme for details",
+    )]
+    include_indirect: bool,
 }
 
 fn api_client() -> Result<reqwest::Client> {
@@ -228,7 +289,7 @@ async fn main() {
         let mut args = Args::parse();
         let cwd = current_dir()?.canonicalize()?;
         let dirs = AppDirs::new(Some("bread-scan"), false).unwrap();
-        let supercontext = Supercontext::new(dirs.cache_dir);
+        let supercontext = Supercontext::new(dirs.cache_dir, args.include_indirect);
         if args.source.is_empty() {
             args.source.push(ArgSource::Project(cwd.clone()));
         }
@@ -240,10 +301,34 @@ async fn main() {
                     let log = log.new(o!("dir" => p.to_string_lossy().to_string()));
                     let mut sub_pool = vec![];
                     process_rust_cargo(&log, &ctx, &mut sub_pool, &p);
-                    process_golang_gomod(&log, &ctx, &p);
+                    process_golang_gomod(&log, &ctx, &mut sub_pool, &p);
                     process_javascript_npm(&log, &ctx, &mut sub_pool, &p);
+                    process_javascript_deno(&log, &ctx, &mut sub_pool, &p);
+                    process_javascript_bun(&log, &ctx, &mut sub_pool, &p);
                     process_python_pyproject(&log, &ctx, &mut sub_pool, &p);
                     process_java_pom(&log, &ctx, &mut sub_pool, &p);
+                    process_java_gradle(&log, &ctx, &mut sub_pool, &p);
+                    process_scala_sbt(&log, &ctx, &mut sub_pool, &p);
+                    process_clojure_lein(&log, &ctx, &mut sub_pool, &p);
+                    process_clojure_deps(&log, &ctx, &mut sub_pool, &p);
+                    process_ruby_bundler(&log, &ctx, &mut sub_pool, &p);
+                    process_php_composer(&log, &ctx, &mut sub_pool, &p);
+                    process_dotnet_nuget(&log, &ctx, &mut sub_pool, &p);
+                    process_elixir_mix(&log, &ctx, &mut sub_pool, &p);
+                    process_erlang_rebar(&log, &ctx, &mut sub_pool, &p);
+                    process_dart_pub(&log, &ctx, &mut sub_pool, &p);
+                    process_swift_pm(&log, &ctx, &mut sub_pool, &p);
+                    process_swift_cocoapods(&log, &ctx, &mut sub_pool, &p);
+                    process_haskell(&log, &ctx, &mut sub_pool, &p);
+                    process_r(&log, &ctx, &mut sub_pool, &p);
+                    process_julia(&log, &ctx, &mut sub_pool, &p);
+                    process_nix_flake(&log, &ctx, &mut sub_pool, &p);
+                    process_ocaml_opam(&log, &ctx, &mut sub_pool, &p);
+                    process_zig(&log, &ctx, &mut sub_pool, &p);
+                    process_cpp_vcpkg(&log, &ctx, &mut sub_pool, &p);
+                    process_cpp_conan(&log, &ctx, &mut sub_pool, &p);
+                    process_git_submodules(&log, &ctx, &mut sub_pool, &p);
+                    process_ci_tooling(&log, &ctx, &mut sub_pool, &p);
                     pool.push(spawn(async move {
                         for f in sub_pool {
                             f.await.unwrap();
@@ -261,6 +346,7 @@ async fn main() {
                                 weight: Some(v.weight),
                             })).collect(),
                             projects: config.projects.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+                            provenance: Default::default(),
                         })
                     }));
                 },
@@ -269,6 +355,7 @@ async fn main() {
                     match o {
                         Os::Debian => pool.push(spawn(os_debian::process(log, supercontext.clone()))),
                         Os::Arch => pool.push(spawn(os_arch::process(log, supercontext.clone()))),
+                        Os::Nixos => pool.push(spawn(os_nixos::process(log, supercontext.clone()))),
                     }
                 },
                 ArgSource::File(p) => {
@@ -284,6 +371,9 @@ async fn main() {
             let w = f.await.unwrap()?;
             working.accounts.extend(w.accounts);
             working.projects.extend(w.projects);
+            for (k, v) in w.provenance {
+                working.provenance.entry(k).or_default().merge(v);
+            }
         }
         if args.dest.is_empty() {
             args.dest.push(ArgDest::ProjectYaml(cwd.clone()));
@@ -0,0 +1,140 @@
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+    path::Path,
+};
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::common::{
+    Context,
+    maybe_read,
+    process_dep_candidates,
+};
+
+#[derive(Deserialize)]
+struct FlakeRef {
+    #[serde(rename = "type")]
+    type_: String,
+    owner: Option<String>,
+    repo: Option<String>,
+    host: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FlakeNode {
+    // Node names, or a `follows` path
+    #[serde(default)]
+    inputs: BTreeMap<String, serde_json::Value>,
+    original: Option<FlakeRef>,
+    locked: Option<FlakeRef>,
+}
+
+#[derive(Deserialize)]
+struct FlakeLock {
+    nodes: BTreeMap<String, FlakeNode>,
+    root: String,
+}
+
+fn flake_ref_url(r: &FlakeRef) -> Option<(String, bool)> {
+    let forge = |default_host: &str| -> Option<(String, bool)> {
+        Some((
+            format!(
+                "https://{}/{}/{}",
+                r.host.as_deref().unwrap_or(default_host),
+                r.owner.as_deref()?,
+                r.repo.as_deref()?
+            ),
+            true,
+        ))
+    };
+    match r.type_.as_str() {
+        "github" => forge("github.com"),
+        "gitlab" => forge("gitlab.com"),
+        "sourcehut" => forge("git.sr.ht"),
+        "git" | "hg" | "tarball" | "file" => {
+            let url = r.url.as_deref()?;
+            let url = url.strip_prefix("git+").unwrap_or(url);
+            if url.starts_with("file:") {
+                return None;
+            }
+            Some((url.to_string(), false))
+        },
+
+        // `path`, and `indirect` (registry) references that weren't resolved
+        _ => None,
+    }
+}
+
+pub fn process_nix_flake(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("flake.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock: FlakeLock = match maybe_read(&lock_path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading flake.lock";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let node_names: BTreeSet<&str> = if ctx.supercontext.include_indirect {
+        lock.nodes.keys().map(|k| k.as_str()).filter(|k| *k != lock.root).collect()
+    } else {
+        // Inputs that follow another input are lists (the path to follow) rather than node names
+        lock
+            .nodes
+            .get(&lock.root)
+            .into_iter()
+            .flat_map(|r| r.inputs.values())
+            .filter_map(|v| v.as_str())
+            .collect()
+    };
+    let mut urls = BTreeSet::new();
+    for name in node_names {
+        let node = match lock.nodes.get(name) {
+            Some(n) => n,
+            None => continue,
+        };
+
+        // Registry references like `nixpkgs` are only resolved in the locked ref
+        let url =
+            node
+                .original
+                .as_ref()
+                .filter(|r| r.type_ != "indirect")
+                .or(node.locked.as_ref())
+                .and_then(flake_ref_url);
+        if let Some(url) = url {
+            urls.insert(url);
+        }
+    }
+    for (url, forge) in urls {
+        if !forge {
+            // Tarball and plain git urls, which may or may not be repos
+            let name = url.clone();
+            process_dep_candidates(&log, ctx, pool, &name, vec![url]);
+            continue;
+        }
+        let ctx = ctx.clone();
+        pool.push(spawn(async move {
+            ctx.add_url(&url).await;
+        }));
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::task::JoinHandle;
+use crate::common::{
+    Context,
+    DepRole,
+    home_dir,
+    maybe_read,
+    process_dep_candidates,
+    version_key,
+};
+
+enum Value {
+    Str(String),
+    List(Vec<Value>),
+    Options(Vec<Value>),
+    // Variables like `with-test`, and bools
+    Ident(String),
+    Other,
+}
+
+fn parse_values(chars: &mut std::iter::Peekable<std::str::Chars>, end: Option<char>) -> Vec<Value> {
+    let mut out = vec![];
+    while let Some(c) = chars.next() {
+        match c {
+            c if Some(c) == end => break,
+            '"' => {
+                // Triple quoted strings (only used for descriptions) parse as several strings, which is harmless
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => s.extend(chars.next()),
+                        '"' => break,
+                        c => s.push(c),
+                    }
+                }
+                out.push(Value::Str(s));
+            },
+            '[' => out.push(Value::List(parse_values(chars, Some(']')))),
+            '{' => out.push(Value::Options(parse_values(chars, Some('}')))),
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            c if c.is_whitespace() => { },
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || "_-+:".contains(*c)) {
+                        break;
+                    }
+                    s.push(*c);
+                    chars.next();
+                }
+                out.push(Value::Ident(s));
+            },
+            _ => out.push(Value::Other),
+        }
+    }
+    out
+}
+
+fn field_start(line: &str) -> Option<(&str, &str)> {
+    let (name, rest) = line.split_once(':')?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return None;
+    }
+    Some((name, rest))
+}
+
+fn parse_opam(text: &str) -> BTreeMap<String, Vec<Value>> {
+    let mut out = BTreeMap::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let (name, rest) = match field_start(line) {
+            Some(f) => f,
+            None => continue,
+        };
+        let mut value = rest.to_string();
+        while let Some(next) = lines.peek() {
+            if field_start(next).is_some() {
+                break;
+            }
+            value.push('\n');
+            value.push_str(lines.next().unwrap());
+        }
+        out.insert(name.to_string(), parse_values(&mut value.chars().peekable(), None));
+    }
+    out
+}
+
+fn field_str<'a>(fields: &'a BTreeMap<String, Vec<Value>>, name: &str) -> Option<&'a str> {
+    match fields.get(name)?.first()? {
+        Value::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn formula_packages<'a>(values: &'a [Value], out: &mut Vec<(&'a str, &'a [Value])>) {
+    for (i, v) in values.iter().enumerate() {
+        match v {
+            Value::Str(name) => {
+                let filter = match values.get(i + 1) {
+                    Some(Value::Options(f)) => f.as_slice(),
+                    _ => &[],
+                };
+                out.push((name, filter));
+            },
+            Value::List(l) => formula_packages(l, out),
+            _ => { },
+        }
+    }
+}
+
+fn repo_dirs() -> Vec<PathBuf> {
+    let root = match env::var_os("OPAMROOT") {
+        Some(r) => PathBuf::from(r),
+        None => match home_dir() {
+            Some(h) => h.join(".opam"),
+            None => return vec![],
+        },
+    };
+    let mut out: Vec<PathBuf> =
+        fs::read_dir(root.join("repo"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path().join("packages"))
+            .filter(|p| p.is_dir())
+            .collect();
+    out.sort();
+    out
+}
+
+fn repository_urls(repo_dirs: &[PathBuf], name: &str, version: Option<&str>) -> Option<Vec<String>> {
+    for dir in repo_dirs {
+        let package_dir = dir.join(name);
+        let version_dir = match version.map(|v| package_dir.join(format!("{}.{}", name, v))).filter(|d| d.exists()) {
+            Some(d) => d,
+            None => {
+                let mut versions: Vec<PathBuf> =
+                    fs::read_dir(&package_dir).into_iter().flatten().flatten().map(|e| e.path()).collect();
+                versions.sort_by_cached_key(|v| {
+                    let dir_name = v.file_name().unwrap().to_string_lossy().to_string();
+                    version_key(dir_name.strip_prefix(&format!("{}.", name)).unwrap_or(&dir_name))
+                });
+                match versions.pop() {
+                    Some(v) => v,
+                    None => continue,
+                }
+            },
+        };
+        let text = match maybe_read(&version_dir.join("opam")) {
+            Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+            _ => continue,
+        };
+        let fields = parse_opam(&text);
+        return Some(
+            [field_str(&fields, "dev-repo"), field_str(&fields, "homepage")]
+                .into_iter()
+                .flatten()
+                .map(|u| u.strip_prefix("git+").unwrap_or(u).to_string())
+                .collect(),
+        );
+    }
+    None
+}
+
+pub fn process_ocaml_opam(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    // Locked files pin the whole dependency tree so are only used for indirect dependencies
+    let include_indirect = ctx.supercontext.include_indirect;
+    let mut opam_paths: Vec<PathBuf> =
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| {
+                let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                name == "opam" || name.ends_with(".opam") || (include_indirect && name.ends_with(".opam.locked")) ||
+                    (include_indirect && name == "opam.locked")
+            })
+            .collect();
+    opam_paths.sort();
+    let repo_dirs = repo_dirs();
+    let mut deps: BTreeMap<String, (DepRole, Option<String>)> = BTreeMap::new();
+    let mut pins = BTreeMap::new();
+    let mut local = vec![];
+    for opam_path in opam_paths {
+        let log = log.new(o!("file" => opam_path.to_string_lossy().to_string()));
+        let text = match maybe_read(&opam_path) {
+            Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading opam file";
+                    "err" => #? e
+                );
+                continue;
+            },
+        };
+        let file_name = opam_path.file_name().unwrap().to_string_lossy().to_string();
+        local.push(file_name.split(".opam").next().unwrap().to_string());
+        let fields = parse_opam(&text);
+        for (field, default_role) in [("depends", DepRole::Runtime), ("depopts", DepRole::Optional)] {
+            let mut packages = vec![];
+            formula_packages(fields.get(field).map(|v| v.as_slice()).unwrap_or_default(), &mut packages);
+            for (name, filter) in packages {
+                // Conf and base packages are placeholders for system libraries and compiler features
+                if name.starts_with("conf-") || name.starts_with("base-") {
+                    continue;
+                }
+                let role =
+                    if filter.iter().any(|v| matches!(v, Value::Ident(i) if i == "with-test" || i == "with-doc")) {
+                        DepRole::Dev
+                    } else {
+                        default_role
+                    };
+
+                // Locked files constrain with `{= "version"}`, other constraints won't match a version so the
+                // latest is used
+                let version = filter.iter().find_map(|v| match v {
+                    Value::Str(s) => Some(s.clone()),
+                    _ => None,
+                });
+                let entry = deps.entry(name.to_string()).or_insert((role, None));
+                entry.0 = entry.0.min(role);
+                if entry.1.is_none() {
+                    entry.1 = version;
+                }
+            }
+        }
+
+        // pin-depends: [ ["name.version" "git+https://..."] ], or a single pin without the outer list
+        let pin_list = match fields.get("pin-depends").and_then(|v| v.first()) {
+            Some(Value::List(l)) if matches!(l.first(), Some(Value::List(_))) => l.iter().collect(),
+            Some(single) => vec![single],
+            None => vec![],
+        };
+        for pin in pin_list {
+            if let Value::List(pin) = pin {
+                let (package, url) = match pin.as_slice() {
+                    [Value::Str(package), Value::Str(url), ..] => (package, url),
+                    _ => continue,
+                };
+                let name = package.split('.').next().unwrap().to_string();
+                pins.insert(name, url.strip_prefix("git+").unwrap_or(url).to_string());
+            }
+        }
+    }
+    for (name, (role, version)) in deps {
+        if local.contains(&name) {
+            continue;
+        }
+        let ctx = ctx.with_role(role);
+        if let Some(url) = pins.get(&name) {
+            process_dep_candidates(log, &ctx, pool, &name, vec![url.clone()]);
+            continue;
+        }
+        match repository_urls(&repo_dirs, &name, version.as_deref()) {
+            Some(candidates) => process_dep_candidates(log, &ctx, pool, &name, candidates),
+            None => warn!(log, "Package not found in local opam repository"; "dep" => name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_opam_fields() {
+        let fields = parse_opam(r#"opam-version: "2.0"
+homepage: "https://example.com/demo"
+dev-repo: "git+https://github.com/a/demo.git" # upstream
+depends: [
+  "ocaml" {>= "4.08"}
+  "dune" {>= "3.0"}
+  ("lwt" | "async")
+  "alcotest" {with-test}
+]
+description: """
+A demo package.
+"""
+"#);
+        assert_eq!(field_str(&fields, "dev-repo"), Some("git+https://github.com/a/demo.git"));
+        assert_eq!(field_str(&fields, "homepage"), Some("https://example.com/demo"));
+        let mut packages = vec![];
+        formula_packages(&fields["depends"], &mut packages);
+        let is_test = |filter: &[Value]| filter.iter().any(|f| matches!(f, Value::Ident(i) if i == "with-test"));
+        let names: Vec<(&str, bool)> = packages.iter().map(|(name, filter)| (*name, is_test(filter))).collect();
+        assert_eq!(names, vec![
+            ("ocaml", false),
+            ("dune", false),
+            ("lwt", false),
+            ("async", false),
+            ("alcotest", true),
+        ]);
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    env,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+use anyhow::{
+    Result,
+    anyhow,
+};
+use serde::Deserialize;
+use slog::{
+    Logger,
+    debug,
+    o,
+    warn,
+};
+use tokio::{
+    process::Command,
+    spawn,
+    sync::Semaphore,
+};
+use crate::{
+    common::{
+        Context,
+        Supercontext,
+        WorkingWeights,
+        home_dir,
+    },
+    aes,
+};
+
+const NIX_FEATURES: &[&str] = &["--extra-experimental-features", "nix-command flakes"];
+
+const NIX_META_APPLY: &str =
+    "p: { homepage = p.meta.homepage or null; src = p.src.url or (builtins.head (p.src.urls or [ null ])); }";
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Installed {
+    Attr {
+        flake: String,
+        attr: String,
+    },
+    Name(String),
+}
+
+fn homepages(v: &serde_json::Value) -> Vec<String> {
+    match v {
+        serde_json::Value::String(s) => vec![s.clone()],
+        serde_json::Value::Array(a) => a.iter().filter_map(|s| s.as_str()).map(|s| s.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+fn store_path_pname(path: &str) -> Option<String> {
+    let name = path.strip_prefix("/nix/store/")?.split_once('-')?.1;
+    let parts: Vec<&str> =
+        name.split('-').take_while(|p| !p.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(true)).collect();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts.join("-"))
+}
+
+fn profile_paths() -> Vec<PathBuf> {
+    let mut out = vec![PathBuf::from("/run/current-system/sw")];
+    if let Ok(user) = env::var("USER") {
+        out.push(PathBuf::from(format!("/etc/profiles/per-user/{}", user)));
+        out.push(PathBuf::from(format!("/nix/var/nix/profiles/per-user/{}/home-manager/home-path", user)));
+    }
+    if let Some(home) = home_dir() {
+        out.push(home.join(".nix-profile"));
+        out.push(home.join(".local/state/nix/profiles/home-manager/home-path"));
+    }
+    out.into_iter().filter(|p| p.exists()).collect()
+}
+
+async fn profile_packages(log: &Logger, installed: &mut BTreeSet<Installed>) {
+    for profile in profile_paths() {
+        let res = match Command::new("nix-store").arg("--query").arg("--references").arg(&profile).output().await {
+            Ok(r) if r.status.success() => r,
+            Ok(r) => {
+                warn!(
+                    log,
+                    "Listing profile packages failed";
+                    "profile" => profile.to_string_lossy().to_string(),
+                    "output" => #? r
+                );
+                continue;
+            },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error running nix-store";
+                    "profile" => profile.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+                continue;
+            },
+        };
+        installed.extend(
+            String::from_utf8_lossy(&res.stdout).lines().filter_map(store_path_pname).map(Installed::Name),
+        );
+    }
+}
+
+async fn nix_profile_packages(log: &Logger, installed: &mut BTreeSet<Installed>) -> Result<()> {
+    #[derive(Deserialize)]
+    struct Element {
+        #[serde(rename = "attrPath")]
+        attr_path: Option<String>,
+        #[serde(rename = "originalUrl")]
+        original_url: Option<String>,
+    }
+
+    // Newer versions key elements by name, older versions list them
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Elements {
+        Named(HashMap<String, Element>),
+        List(Vec<Element>),
+    }
+
+    #[derive(Deserialize)]
+    struct Profile {
+        elements: Elements,
+    }
+
+    let res = Command::new("nix").args(NIX_FEATURES).args(["profile", "list", "--json"]).output().await?;
+    if !res.status.success() {
+        debug!(log, "Listing nix profile failed, skipping"; "output" => #? res);
+        return Ok(());
+    }
+    let profile: Profile = serde_json::from_slice(&res.stdout)?;
+    let elements = match profile.elements {
+        Elements::Named(e) => e.into_values().collect(),
+        Elements::List(e) => e,
+    };
+    for e in elements {
+        let (attr, flake) = match (e.attr_path, e.original_url) {
+            (Some(a), Some(f)) => (a, f),
+            _ => continue,
+        };
+
+        // Attribute paths are fully qualified like `legacyPackages.x86_64-linux.hello`
+        let attr = match attr.splitn(3, '.').collect::<Vec<_>>()[..] {
+            ["legacyPackages" | "packages", _, a] => a.to_string(),
+            _ => attr,
+        };
+        installed.insert(Installed::Attr {
+            flake,
+            attr,
+        });
+    }
+    Ok(())
+}
+
+async fn nixpkgs_path() -> Result<PathBuf> {
+    let res = Command::new("nix-instantiate").args(["--find-file", "nixpkgs"]).output().await?;
+    if !res.status.success() {
+        return Err(anyhow!("Locating nixpkgs failed:\n{:?}", res));
+    }
+    Ok(fs::canonicalize(String::from_utf8_lossy(&res.stdout).trim())?)
+}
+
+async fn nixpkgs_index(log: &Logger, ctx: &Context) -> Result<HashMap<String, (String, Vec<String>)>> {
+    let nixpkgs = nixpkgs_path().await?;
+    let cache_key = format!("nixos-nixpkgs-index-{}", nixpkgs.to_string_lossy());
+    if let Some(i) = ctx.cache_get(log, &cache_key).await {
+        return Ok(i);
+    }
+
+    #[derive(Deserialize)]
+    struct Package {
+        pname: Option<String>,
+        #[serde(default)]
+        meta: HashMap<String, serde_json::Value>,
+    }
+
+    let res =
+        Command::new("nix-env")
+            .args(["--query", "--available", "--json", "--meta", "--file"])
+            .arg(&nixpkgs)
+            .output()
+            .await?;
+    if !res.status.success() {
+        return Err(anyhow!("Listing nixpkgs packages failed:\n{:?}", res));
+    }
+    let packages: HashMap<String, Package> = serde_json::from_slice(&res.stdout)?;
+    let mut index: HashMap<String, (String, Vec<String>)> = HashMap::new();
+    for (attr, package) in packages {
+        let pname = match package.pname {
+            Some(p) => p,
+            None => continue,
+        };
+        let homepage = package.meta.get("homepage").map(homepages).unwrap_or_default();
+
+        // Prefer the shortest attribute path, e.g. `hello` over `pkgsStatic.hello`
+        match index.get(&pname) {
+            Some((a, _)) if a.len() <= attr.len() => { },
+            _ => {
+                index.insert(pname, (attr, homepage));
+            },
+        }
+    }
+    ctx.cache_put(log, &cache_key, &index).await;
+    Ok(index)
+}
+
+async fn eval_candidates(
+    log: &Logger,
+    ctx: &Context,
+    evals: &Semaphore,
+    flake: &str,
+    attr: &str,
+) -> Result<Vec<String>> {
+    let cache_key = format!("nixos-{}#{}", flake, attr);
+    if let Some(c) = ctx.cache_get(log, &cache_key).await {
+        return Ok(c);
+    }
+
+    #[derive(Deserialize)]
+    struct Meta {
+        homepage: serde_json::Value,
+        src: Option<String>,
+    }
+
+    let permit = evals.acquire().await?;
+    let res =
+        Command::new("nix")
+            .args(NIX_FEATURES)
+            .arg("eval")
+            .arg("--json")
+            .arg(format!("{}#{}", flake, attr))
+            .arg("--apply")
+            .arg(NIX_META_APPLY)
+            .output()
+            .await?;
+    drop(permit);
+    let candidates = if res.status.success() {
+        let meta: Meta = serde_json::from_slice(&res.stdout)?;
+        homepages(&meta.homepage).into_iter().chain(meta.src).collect()
+    } else {
+        debug!(log, "Evaluating package failed"; "output" => #? res);
+        vec![]
+    };
+    ctx.cache_put(log, &cache_key, &candidates).await;
+    Ok(candidates)
+}
+
+pub async fn process(log: Logger, supercontext: Supercontext) -> Result<WorkingWeights> {
+    let ctx = Context::new(supercontext.clone());
+    let mut installed = BTreeSet::new();
+    profile_packages(&log, &mut installed).await;
+    if let Err(e) = nix_profile_packages(&log, &mut installed).await {
+        warn!(
+            log,
+            "Error listing nix profile packages";
+            "err" => #? e
+        );
+    }
+    let index = if installed.iter().any(|i| matches!(i, Installed::Name(_))) {
+        match nixpkgs_index(&log, &ctx).await {
+            Ok(i) => i,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error indexing nixpkgs, falling back to evaluating packages by name";
+                    "err" => #? e
+                );
+                HashMap::new()
+            },
+        }
+    } else {
+        HashMap::new()
+    };
+    // Evaluations are memory hungry
+    let evals = Arc::new(Semaphore::new(thread::available_parallelism().map(|n| n.get()).unwrap_or(4)));
+    let mut sub_pool = vec![];
+    for i in installed {
+        let (flake, attr, mut candidates) = match i {
+            Installed::Attr { flake, attr } => (flake, attr, vec![]),
+            Installed::Name(name) => match index.get(&name) {
+                Some((attr, homepages)) => ("nixpkgs".to_string(), attr.clone(), homepages.clone()),
+                None => ("nixpkgs".to_string(), name, vec![]),
+            },
+        };
+        let log = log.new(o!("package" => attr.clone()));
+        let ctx = ctx.clone();
+        let evals = evals.clone();
+        sub_pool.push(spawn(async move {
+            match aes!({
+                for c in &candidates {
+                    if ctx.maybe_add_url(&log, c).await {
+                        return Ok(());
+                    }
+                }
+                candidates = eval_candidates(&log, &ctx, &evals, &flake, &attr).await?;
+                for c in &candidates {
+                    if ctx.maybe_add_url(&log, c).await {
+                        return Ok(());
+                    }
+                }
+                debug!(log, "No repo-ish url found for package");
+                Ok(())
+            }).await {
+                Ok(_) => { },
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error looking up repo for package";
+                        "err" => #? e
+                    );
+                },
+            }
+        }));
+    }
+    for f in sub_pool {
+        f.await.unwrap();
+    }
+    let w: WorkingWeights = ctx.config.lock().unwrap().clone();
+    Ok(w)
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    path::Path,
+};
+use anyhow::{
+    Context as _,
+    Result,
+};
+use reqwest::StatusCode;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        DepRole,
+        is_gitlab_url,
+        maybe_read,
+    },
+    aes,
+};
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    require: BTreeMap<String, String>,
+    #[serde(rename = "require-dev", default)]
+    require_dev: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+struct PackageSourceRef {
+    url: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct PackageSupport {
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PackageFunding {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    #[serde(default)]
+    source: PackageSourceRef,
+    #[serde(default)]
+    support: PackageSupport,
+    homepage: Option<String>,
+    #[serde(default)]
+    funding: Vec<PackageFunding>,
+}
+
+#[derive(Deserialize)]
+struct Lock {
+    #[serde(default)]
+    packages: Vec<Package>,
+    #[serde(rename = "packages-dev", default)]
+    packages_dev: Vec<Package>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PackageSource {
+    candidates: Vec<String>,
+    funding: Vec<String>,
+    #[serde(default)]
+    vcs_url: Option<String>,
+}
+
+impl From<Package> for PackageSource {
+    fn from(p: Package) -> Self {
+        PackageSource {
+            candidates: p.source.url.clone().into_iter().chain(p.support.source).chain(p.homepage).collect(),
+            funding: p.funding.into_iter().map(|f| f.url).collect(),
+            vcs_url: p.source.url,
+        }
+    }
+}
+
+async fn add_package_source(log: &Logger, ctx: &Context, source: PackageSource) -> bool {
+    for c in source.candidates.iter().filter(|c| !is_gitlab_url(c)) {
+        if ctx.maybe_add_funded_url(log, c, &source.funding).await {
+            return true;
+        }
+    }
+
+    // Repos that aren't on a known forge, like self-hosted GitLab or Gitea
+    match &source.vcs_url {
+        Some(url) => ctx.add_repo_url(log, url).await,
+        None => false,
+    }
+}
+
+fn process_locked_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, package: Package) {
+    let log = log.new(o!("dep" => package.name.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        if !add_package_source(&log, &ctx, package.into()).await {
+            warn!(log, "No repo-ish url found in dep metadata");
+        }
+    }));
+}
+
+fn process_packagist_dep(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, dep: String) {
+    let log = log.new(o!("dep" => dep.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("php-packagist-{}", dep);
+            let source = match ctx.cache_get::<PackageSource>(&log, &cache_key).await {
+                Some(s) => s,
+                None => {
+                    #[derive(Deserialize)]
+                    struct Metadata {
+                        packages: HashMap<String, Vec<Package>>,
+                    }
+
+                    let resp =
+                        ctx.http_get(&format!("https://repo.packagist.org/p2/{}.json", dep)).await?.send().await?;
+                    let source = if resp.status() == StatusCode::NOT_FOUND {
+                        PackageSource::default()
+                    } else {
+                        let mut meta: Metadata = resp.error_for_status()?.json().await?;
+
+                        // Versions are listed newest first, later entries are minified and may omit fields
+                        meta
+                            .packages
+                            .remove(&dep)
+                            .and_then(|v| v.into_iter().next())
+                            .map(|p| p.into())
+                            .unwrap_or_default()
+                    };
+                    ctx.cache_put(&log, &cache_key, &source).await;
+                    source
+                },
+            };
+            if !add_package_source(&log, &ctx, source).await {
+                warn!(log, "No repo-ish url found in dep metadata");
+            }
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn load_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Option<T>> {
+    Ok(match maybe_read(path)? {
+        Some(b) => Some(serde_json::from_slice(&b).context("Error parsing json")?),
+        None => None,
+    })
+}
+
+pub fn process_php_composer(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let manifest_path = path.join("composer.json");
+    let lock_path = path.join("composer.lock");
+    let manifest = match load_json::<Manifest>(&manifest_path) {
+        Ok(m) => m,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading composer.json";
+                "file" => manifest_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    };
+    let lock = match load_json::<Lock>(&lock_path) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading composer.lock";
+                "file" => lock_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            None
+        },
+    };
+    match (manifest, lock) {
+        (manifest, Some(lock)) => {
+            let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+            let include_all = ctx.supercontext.include_indirect || manifest.is_none();
+            let (require, require_dev) = match manifest {
+                Some(m) => (m.require, m.require_dev),
+                None => Default::default(),
+            };
+            for (packages, role) in [(lock.packages, DepRole::Runtime), (lock.packages_dev, DepRole::Dev)] {
+                let ctx = ctx.with_role(role);
+                for package in packages {
+                    if !include_all && !require.contains_key(&package.name) &&
+                        !require_dev.contains_key(&package.name) {
+                        continue;
+                    }
+                    process_locked_dep(&log, &ctx, pool, package);
+                }
+            }
+        },
+        (Some(manifest), None) => {
+            let log = log.new(o!("file" => manifest_path.to_string_lossy().to_string()));
+            for (deps, role) in [(manifest.require, DepRole::Runtime), (manifest.require_dev, DepRole::Dev)] {
+                let ctx = ctx.with_role(role);
+                for dep in deps.into_keys() {
+                    // Platform requirements (`php`, `ext-json`, etc.) aren't packages
+                    if !dep.contains('/') {
+                        continue;
+                    }
+                    process_packagist_dep(&log, &ctx, pool, dep);
+                }
+            }
+        },
+        (None, None) => { },
+    }
+}
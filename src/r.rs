@@ -0,0 +1,369 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+        HashSet,
+    },
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        DepRole,
+        home_dir,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+const CRANDB: &str = "https://crandb.r-pkg.org";
+
+const BASE_PACKAGES: &[&str] = &[
+    "R",
+    "base",
+    "compiler",
+    "datasets",
+    "graphics",
+    "grDevices",
+    "grid",
+    "methods",
+    "parallel",
+    "splines",
+    "stats",
+    "stats4",
+    "tcltk",
+    "tools",
+    "utils",
+];
+
+fn parse_dcf(text: &str) -> HashMap<String, String> {
+    let mut out: HashMap<String, String> = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in text.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some(v) = last.as_ref().and_then(|k| out.get_mut(k)) {
+                v.push(' ');
+                v.push_str(line.trim());
+            }
+            continue;
+        }
+        match line.split_once(':') {
+            Some((k, v)) => {
+                out.insert(k.trim().to_string(), v.trim().to_string());
+                last = Some(k.trim().to_string());
+            },
+            None => last = None,
+        }
+    }
+    out
+}
+
+fn dep_names(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split(',').filter_map(|d| {
+        let name = d.split('(').next().unwrap().trim();
+        if name.is_empty() || BASE_PACKAGES.contains(&name) {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    })
+}
+
+fn description_urls(url: Option<&str>, bug_reports: Option<&str>) -> Vec<String> {
+    url
+        .into_iter()
+        .flat_map(|u| u.split([',', ' ', '\n']))
+        .chain(bug_reports)
+        .map(|u| u.trim())
+        .filter(|u| !u.is_empty())
+        .map(|u| u.to_string())
+        .collect()
+}
+
+fn library_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![];
+    let children =
+        |p: PathBuf| fs::read_dir(p).into_iter().flatten().flatten().map(|e| e.path()).collect::<Vec<_>>();
+
+    // renv/library/<R version>/<platform>
+    for v in children(path.join("renv/library")) {
+        out.extend(children(v));
+    }
+    for var in ["R_LIBS", "R_LIBS_USER"] {
+        if let Some(v) = env::var_os(var) {
+            out.extend(env::split_paths(&v));
+        }
+    }
+
+    // ~/R/<platform>-library/<R version>
+    if let Some(home) = home_dir() {
+        for p in children(home.join("R")) {
+            out.extend(children(p));
+        }
+    }
+    out.extend(
+        ["/usr/local/lib/R/site-library", "/usr/lib/R/site-library", "/usr/lib/R/library"].map(PathBuf::from),
+    );
+    out
+}
+
+fn local_description_urls(library_dirs: &[PathBuf], name: &str) -> Option<Vec<String>> {
+    for dir in library_dirs {
+        let text = match maybe_read(&dir.join(name).join("DESCRIPTION")) {
+            Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+            _ => continue,
+        };
+        let desc = parse_dcf(&text);
+        return Some(
+            description_urls(desc.get("URL").map(|s| s.as_str()), desc.get("BugReports").map(|s| s.as_str())),
+        );
+    }
+    None
+}
+
+fn process_dep(
+    log: &Logger,
+    ctx: &Context,
+    pool: &mut Vec<JoinHandle<()>>,
+    library_dirs: &[PathBuf],
+    repo: Option<String>,
+    name: String,
+) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    let local = local_description_urls(library_dirs, &name);
+    pool.push(spawn(async move {
+        match aes!({
+            if let Some(candidates) = local {
+                for c in candidates {
+                    if ctx.maybe_add_url(&log, &c).await {
+                        return Ok(());
+                    }
+                }
+            }
+
+            // r-universe serves package metadata at `/api/packages/<name>`, everything else is looked up in CRAN
+            let (cache_key, meta_url) = match repo.filter(|r| r.contains(".r-universe.dev")) {
+                Some(universe) => (
+                    format!("r-{}-{}", universe, name),
+                    format!("{}/api/packages/{}", universe.trim_end_matches('/'), name),
+                ),
+                None => (format!("r-cran-{}", name), format!("{}/{}", CRANDB, name)),
+            };
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    #[derive(Deserialize)]
+                    struct Description {
+                        #[serde(rename = "URL")]
+                        url: Option<String>,
+                        #[serde(rename = "BugReports")]
+                        bug_reports: Option<String>,
+                    }
+
+                    let resp = ctx.http_get(&meta_url).await?.send().await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        let desc: Description = resp.error_for_status()?.json().await?;
+                        description_urls(desc.url.as_deref(), desc.bug_reports.as_deref())
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+#[derive(Deserialize)]
+struct RenvRepository {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "URL")]
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct RenvR {
+    #[serde(rename = "Repositories", default)]
+    repositories: Vec<RenvRepository>,
+}
+
+#[derive(Deserialize)]
+struct RenvPackage {
+    #[serde(rename = "Repository")]
+    repository: Option<String>,
+    #[serde(rename = "RemoteType")]
+    remote_type: Option<String>,
+    #[serde(rename = "RemoteHost")]
+    remote_host: Option<String>,
+    #[serde(rename = "RemoteUsername")]
+    remote_username: Option<String>,
+    #[serde(rename = "RemoteRepo")]
+    remote_repo: Option<String>,
+    #[serde(rename = "RemoteUrl")]
+    remote_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RenvLock {
+    #[serde(rename = "R")]
+    r: Option<RenvR>,
+    #[serde(rename = "Packages", default)]
+    packages: BTreeMap<String, RenvPackage>,
+}
+
+fn renv_remote_url(package: &RenvPackage) -> Option<String> {
+    let remote_type = package.remote_type.as_deref()?;
+    let default_host = match remote_type {
+        "github" => "github.com",
+        "gitlab" => "gitlab.com",
+        "bitbucket" => "bitbucket.org",
+        "git" => return package.remote_url.clone(),
+        _ => return None,
+    };
+
+    // Hosts are api endpoints like `api.github.com` or `api.bitbucket.org/2.0`
+    let host = match package.remote_host.as_deref() {
+        Some(h) => h.trim_start_matches("api.").split('/').next().unwrap(),
+        None => default_host,
+    };
+    Some(format!("https://{}/{}/{}", host, package.remote_username.as_deref()?, package.remote_repo.as_deref()?))
+}
+
+pub fn process_r(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let library_dirs = library_dirs(path);
+
+    // Package DESCRIPTION
+    let description_path = path.join("DESCRIPTION");
+    let mut direct = HashSet::new();
+    let has_description = match maybe_read(&description_path) {
+        Ok(Some(b)) => {
+            let log = log.new(o!("file" => description_path.to_string_lossy().to_string()));
+            let desc = parse_dcf(&String::from_utf8_lossy(&b));
+            for (field, role) in [
+                ("Depends", DepRole::Runtime),
+                ("Imports", DepRole::Runtime),
+                ("LinkingTo", DepRole::Runtime),
+                ("Suggests", DepRole::Optional),
+            ] {
+                let ctx = ctx.with_role(role);
+                for name in desc.get(field).into_iter().flat_map(|v| dep_names(v)) {
+                    if direct.insert(name.clone()) {
+                        process_dep(&log, &ctx, pool, &library_dirs, None, name);
+                    }
+                }
+            }
+            true
+        },
+        Ok(None) => false,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading DESCRIPTION";
+                "file" => description_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            false
+        },
+    };
+
+    // renv locks every installed package, which is all there is to go on without a DESCRIPTION
+    if has_description && !ctx.supercontext.include_indirect {
+        return;
+    }
+    let lock_path = path.join("renv.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock: RenvLock = match maybe_read(&lock_path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading renv.lock";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let repositories: HashMap<String, String> =
+        lock.r.into_iter().flat_map(|r| r.repositories).map(|r| (r.name, r.url)).collect();
+    for (name, package) in lock.packages {
+        if BASE_PACKAGES.contains(&name.as_str()) || direct.contains(&name) {
+            continue;
+        }
+        if let Some(url) = renv_remote_url(&package) {
+            process_git_dep(&log, ctx, pool, url);
+            continue;
+        }
+        let repo = package.repository.map(|r| repositories.get(&r).cloned().unwrap_or(r));
+        process_dep(&log, ctx, pool, &library_dirs, repo, name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dcf_continuations() {
+        let desc = parse_dcf("\
+Package: demo
+Imports:
+    dplyr (>= 1.0.0),
+\trlang
+URL: https://github.com/a/demo,
+  https://demo.example.com
+");
+        assert_eq!(desc["Package"], "demo");
+        assert_eq!(dep_names(&desc["Imports"]).collect::<Vec<_>>(), vec!["dplyr", "rlang"]);
+        let urls = description_urls(desc.get("URL").map(|s| s.as_str()), Some("https://github.com/a/demo/issues"));
+        assert_eq!(urls, vec![
+            "https://github.com/a/demo",
+            "https://demo.example.com",
+            "https://github.com/a/demo/issues",
+        ]);
+    }
+
+    #[test]
+    fn dep_names_skip_base_packages() {
+        assert_eq!(dep_names("R (>= 4.0), methods, jsonlite").collect::<Vec<_>>(), vec!["jsonlite"]);
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashSet,
+    },
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use reqwest::{
+    StatusCode,
+    header::{
+        self,
+        HeaderValue,
+    },
+};
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use structre::structre;
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+#[derive(Clone)]
+enum GemSource {
+    Rubygems,
+    Git(String),
+    Path,
+}
+
+#[structre(r#"^\s*gem\s*\(?\s*['"](?P<name>[^'"]+)['"]"#)]
+struct GemfileGem {
+    name: String,
+}
+
+#[structre(r#"(?:git|github):\s*['"](?P<value>[^'"]+)['"]"#)]
+struct GemfileGit {
+    value: String,
+}
+
+#[structre(r#"\.homepage\s*=\s*"(?P<url>[^"]+)""#)]
+struct GemspecHomepage {
+    url: String,
+}
+
+#[structre(r#""source_code_uri"\s*=>\s*"(?P<url>[^"]+)""#)]
+struct GemspecSourceCode {
+    url: String,
+}
+
+fn spec_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![];
+    if let Ok(entries) = fs::read_dir(path.join("vendor/bundle/ruby")) {
+        for e in entries.flatten() {
+            out.push(e.path().join("specifications"));
+        }
+    }
+    if let Some(gem_home) = env::var_os("GEM_HOME") {
+        out.push(PathBuf::from(gem_home).join("specifications"));
+    }
+    out
+}
+
+fn local_gemspec_urls(spec_dirs: &[PathBuf], name: &str, version: Option<&str>) -> Option<Vec<String>> {
+    let mut spec_path = None;
+    for dir in spec_dirs {
+        if let Some(version) = version {
+            let p = dir.join(format!("{}-{}.gemspec", name, version));
+            if p.exists() {
+                spec_path = Some(p);
+                break;
+            }
+            continue;
+        }
+        let prefix = format!("{}-", name);
+        for e in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let file_name = e.file_name().to_string_lossy().to_string();
+            let is_version =
+                file_name
+                    .strip_prefix(&prefix)
+                    .and_then(|r| r.chars().next())
+                    .map(|c| c.is_ascii_digit())
+                    .unwrap_or(false);
+            if is_version && file_name.ends_with(".gemspec") {
+                spec_path = Some(e.path());
+                break;
+            }
+        }
+        if spec_path.is_some() {
+            break;
+        }
+    }
+    let text = String::from_utf8_lossy(&maybe_read(&spec_path?).ok()??).to_string();
+    let parse_homepage = GemspecHomepageFromRegex::new();
+    let parse_source = GemspecSourceCodeFromRegex::new();
+    let mut source = vec![];
+    let mut homepage = vec![];
+    for line in text.lines() {
+        source.extend(parse_source.parse(line).ok().map(|m| m.url));
+        homepage.extend(parse_homepage.parse(line).ok().map(|m| m.url));
+    }
+    source.extend(homepage);
+    Some(source)
+}
+
+fn process_dep(
+    log: &Logger,
+    ctx: &Context,
+    pool: &mut Vec<JoinHandle<()>>,
+    spec_dirs: &[PathBuf],
+    name: String,
+    version: Option<String>,
+) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    let local = local_gemspec_urls(spec_dirs, &name, version.as_deref());
+    pool.push(spawn(async move {
+        match aes!({
+            if let Some(candidates) = local {
+                for c in candidates {
+                    if ctx.maybe_add_url(&log, &c).await {
+                        return Ok(());
+                    }
+                }
+            }
+            let cache_key = format!("ruby-{}", name);
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    #[derive(Deserialize)]
+                    struct Gem {
+                        source_code_uri: Option<String>,
+                        homepage_uri: Option<String>,
+                    }
+
+                    let resp =
+                        ctx
+                            .http_get(&format!("https://rubygems.org/api/v1/gems/{}.json", name))
+                            .await?
+                            .header(header::ACCEPT, HeaderValue::from_static("application/json"))
+                            .send()
+                            .await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        let gem: Gem = resp.error_for_status()?.json().await?;
+                        gem.source_code_uri.into_iter().chain(gem.homepage_uri).collect()
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+#[derive(Default)]
+struct Lock {
+    gems: BTreeMap<String, (GemSource, String)>,
+    direct: Vec<String>,
+}
+
+fn parse_lock(text: &str) -> Lock {
+    let mut out = Lock::default();
+    let mut section = "";
+    let mut remote = String::new();
+    for line in text.lines() {
+        if !line.starts_with(' ') {
+            section = line.trim();
+            remote.clear();
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let line = line.trim();
+        match section {
+            "GEM" | "GIT" | "PATH" => {
+                if let Some(r) = line.strip_prefix("remote: ") {
+                    remote = r.to_string();
+                    continue;
+                }
+                if indent != 4 {
+                    continue;
+                }
+                let (name, version) = match line.split_once(" (") {
+                    Some((n, v)) => (n, v.trim_end_matches(')')),
+                    None => continue,
+                };
+                let source = match section {
+                    "GIT" => GemSource::Git(remote.clone()),
+                    "PATH" => GemSource::Path,
+                    _ => GemSource::Rubygems,
+                };
+                out.gems.insert(name.to_string(), (source, version.to_string()));
+            },
+            "DEPENDENCIES" if indent == 2 => {
+                out.direct.push(line.split([' ', '!']).next().unwrap().to_string());
+            },
+            _ => { },
+        }
+    }
+    out
+}
+
+fn parse_gemfile(text: &str) -> Vec<(String, GemSource)> {
+    let parse_gem = GemfileGemFromRegex::new();
+    let parse_git = GemfileGitFromRegex::new();
+    let mut out = vec![];
+    for line in text.lines() {
+        let gem = match parse_gem.parse(line) {
+            Ok(g) => g,
+            Err(_) => continue,
+        };
+        let source = if let Ok(git) = parse_git.parse(line) {
+            if git.value.contains("://") || git.value.starts_with("git@") {
+                GemSource::Git(git.value)
+            } else {
+                GemSource::Git(format!("https://github.com/{}", git.value))
+            }
+        } else if line.contains("path:") {
+            GemSource::Path
+        } else {
+            GemSource::Rubygems
+        };
+        out.push((gem.name, source));
+    }
+    out
+}
+
+pub fn process_ruby_bundler(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("Gemfile.lock");
+    let gemfile_path = path.join("Gemfile");
+    let mut deps: Vec<(String, GemSource, Option<String>)> = vec![];
+    let log = match maybe_read(&lock_path) {
+        Ok(Some(b)) => {
+            let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+            let lock = parse_lock(&String::from_utf8_lossy(&b));
+            let names = if ctx.supercontext.include_indirect {
+                lock.gems.keys().cloned().collect()
+            } else {
+                lock.direct
+            };
+            let mut seen = HashSet::new();
+            for name in names {
+                match lock.gems.get(&name) {
+                    Some((source, version)) => {
+                        if seen.insert(name.clone()) {
+                            deps.push((name, source.clone(), Some(version.clone())));
+                        }
+                    },
+                    None => warn!(log, "Dependency missing from lockfile specs"; "dep" => name),
+                }
+            }
+            log
+        },
+        Ok(None) => {
+            let log = log.new(o!("file" => gemfile_path.to_string_lossy().to_string()));
+            match maybe_read(&gemfile_path) {
+                Ok(Some(b)) => {
+                    for (name, source) in parse_gemfile(&String::from_utf8_lossy(&b)) {
+                        deps.push((name, source, None));
+                    }
+                },
+                Ok(None) => return,
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Error loading Gemfile";
+                        "err" => #? e
+                    );
+                    return;
+                },
+            }
+            log
+        },
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading Gemfile.lock";
+                "file" => lock_path.to_string_lossy().to_string(),
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let spec_dirs = spec_dirs(path);
+    for (name, source, version) in deps {
+        match source {
+            GemSource::Rubygems => {
+                // Platform specific gems are locked like `1.15.0-x86_64-linux`
+                let version = version.map(|v| v.split('-').next().unwrap().to_string());
+                process_dep(&log, ctx, pool, &spec_dirs, name, version);
+            },
+            GemSource::Git(url) => process_git_dep(&log, ctx, pool, url),
+            GemSource::Path => { },
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use slog::{
+    Logger,
+    o,
+    warn,
+    debug,
+};
+use structre::structre;
+use tokio::task::JoinHandle;
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+    },
+    java,
+};
+
+#[structre(r#"scalaVersion\s*:=\s*"(?P<version>[^"]+)""#)]
+struct ScalaVersion {
+    version: String,
+}
+
+#[structre(r#"\bval\s+(?P<name>\w+)\s*=\s*"(?P<value>[^"]*)""#)]
+struct StringVal {
+    name: String,
+    value: String,
+}
+
+// `%%` and `%%%` are cross-versioned, and the version may be a `val`
+#[structre(r#""(?P<group>[^"]+)"\s*(?P<cross>%{1,3})\s*"(?P<name>[^"]+)"\s*%\s*(?P<version>"[^"]+"|[A-Za-z_][\w.]*)"#)]
+struct Module {
+    group: String,
+    cross: String,
+    name: String,
+    version: String,
+}
+
+fn sbt_files(log: &Logger, path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![path.join("build.sbt")];
+    match fs::read_dir(path.join("project")) {
+        Ok(entries) => {
+            for e in entries.flatten() {
+                let p = e.path();
+                if p.extension().map(|e| e == "sbt" || e == "scala").unwrap_or(false) {
+                    out.push(p);
+                }
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => { },
+        Err(e) => {
+            warn!(
+                log,
+                "Error listing sbt project directory";
+                "err" => #? e
+            );
+        },
+    }
+    out
+}
+
+pub fn process_scala_sbt(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let mut sources = vec![];
+    for file in sbt_files(log, path) {
+        match maybe_read(&file) {
+            Ok(None) => { },
+            Ok(Some(b)) => sources.push((file, String::from_utf8_lossy(&b).to_string())),
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading sbt build file";
+                    "file" => file.to_string_lossy().to_string(),
+                    "err" => #? e
+                );
+            },
+        }
+    }
+    if sources.is_empty() {
+        return;
+    }
+    let parse_scala_version = ScalaVersionFromRegex::new();
+    let parse_val = StringValFromRegex::new();
+    let parse_module = ModuleFromRegex::new();
+
+    // Gather scala version and string vals from all files first since they're often defined separately
+    let mut scala_version = None;
+    let mut vals = HashMap::new();
+    for (_, text) in &sources {
+        for line in text.lines() {
+            if let Ok(v) = parse_scala_version.parse(line) {
+                scala_version.get_or_insert(v.version);
+            }
+            if let Ok(v) = parse_val.parse(line) {
+                vals.insert(v.name, v.value);
+            }
+        }
+    }
+    let scala_binary = match scala_version {
+        Some(v) if v.starts_with("3.") => "3".to_string(),
+        Some(v) => v.splitn(3, '.').take(2).collect::<Vec<_>>().join("."),
+        None => "2.13".to_string(),
+    };
+    let mut deps = BTreeSet::new();
+    for (file, text) in &sources {
+        let log = log.new(o!("file" => file.to_string_lossy().to_string()));
+        let is_plugins = file.extension().map(|e| e == "sbt").unwrap_or(false) && file.starts_with(path.join("project"));
+        for line in text.lines() {
+            // Only one match is found per parse, so split up lists like `Seq(a % b % c, d % e % f)`
+            for piece in line.split(',') {
+                let m = match parse_module.parse(piece) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+                let version = if let Some(v) = m.version.strip_prefix('"') {
+                    v.trim_end_matches('"').to_string()
+                } else {
+                    match vals.get(m.version.rsplit('.').next().unwrap()) {
+                        Some(v) => v.clone(),
+                        None => {
+                            debug!(log, "Unable to resolve version reference"; "ref" => m.version);
+                            continue;
+                        },
+                    }
+                };
+                let name = if is_plugins && piece.contains("addSbtPlugin") {
+                    // sbt 1 plugins are published for scala 2.12
+                    format!("{}_2.12_1.0", m.name)
+                } else {
+                    match m.cross.as_str() {
+                        "%%" => format!("{}_{}", m.name, scala_binary),
+                        "%%%" => format!("{}_sjs1_{}", m.name, scala_binary),
+                        _ => m.name,
+                    }
+                };
+                deps.insert((m.group, name, version));
+            }
+        }
+    }
+    for (group, name, version) in deps {
+        java::process_dep(log, ctx, pool, &[], group, name, version);
+    }
+}
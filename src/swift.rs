@@ -0,0 +1,213 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::{
+    common::{
+        Context,
+        maybe_read,
+        process_git_dep,
+    },
+    aes,
+};
+
+#[derive(Deserialize)]
+struct PinV1 {
+    #[serde(rename = "repositoryURL")]
+    repository_url: String,
+}
+
+#[derive(Deserialize)]
+struct ObjectV1 {
+    pins: Vec<PinV1>,
+}
+
+#[derive(Deserialize)]
+struct Pin {
+    #[serde(default)]
+    kind: String,
+    location: String,
+}
+
+// Version 1 nests pins under `object`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Resolved {
+    V1 {
+        object: ObjectV1,
+    },
+    V2 {
+        pins: Vec<Pin>,
+    },
+}
+
+fn resolved_paths(path: &Path) -> Vec<PathBuf> {
+    let mut out = vec![path.join("Package.resolved")];
+    for e in fs::read_dir(path).into_iter().flatten().flatten() {
+        let p = e.path();
+        match p.extension().and_then(|e| e.to_str()) {
+            Some("xcodeproj") => out.push(p.join("project.xcworkspace/xcshareddata/swiftpm/Package.resolved")),
+            Some("xcworkspace") => out.push(p.join("xcshareddata/swiftpm/Package.resolved")),
+            _ => { },
+        }
+    }
+    out
+}
+
+pub fn process_swift_pm(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let mut urls = BTreeSet::new();
+    for resolved_path in resolved_paths(path) {
+        let log = log.new(o!("file" => resolved_path.to_string_lossy().to_string()));
+        let resolved: Resolved = match maybe_read(&resolved_path).and_then(|b| match b {
+            Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+            None => Ok(None),
+        }) {
+            Ok(Some(r)) => r,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error loading Package.resolved";
+                    "err" => #? e
+                );
+                continue;
+            },
+        };
+        match resolved {
+            Resolved::V1 { object } => urls.extend(object.pins.into_iter().map(|p| p.repository_url)),
+            Resolved::V2 { pins } => {
+                // Registry pins have a package id rather than a url, local pins are paths
+                urls.extend(
+                    pins
+                        .into_iter()
+                        .filter(|p| ["", "remoteSourceControl"].contains(&p.kind.as_str()))
+                        .map(|p| p.location),
+                );
+            },
+        }
+    }
+    for url in urls {
+        process_git_dep(log, ctx, pool, url);
+    }
+}
+
+fn process_pod(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, name: String) {
+    let log = log.new(o!("dep" => name.clone()));
+    let ctx = ctx.clone();
+    pool.push(spawn(async move {
+        match aes!({
+            let cache_key = format!("swift-cocoapods-{}", name);
+            let candidates = match ctx.cache_get::<Vec<String>>(&log, &cache_key).await {
+                Some(c) => c,
+                None => {
+                    #[derive(Deserialize, Default)]
+                    struct Source {
+                        git: Option<String>,
+                    }
+
+                    #[derive(Deserialize)]
+                    struct Spec {
+                        #[serde(default)]
+                        source: Source,
+                        homepage: Option<String>,
+                    }
+
+                    let resp =
+                        ctx
+                            .http_get(&format!("https://trunk.cocoapods.org/api/v1/pods/{}/specs/latest", name))
+                            .await?
+                            .send()
+                            .await?;
+                    let candidates = if resp.status() == StatusCode::NOT_FOUND {
+                        vec![]
+                    } else {
+                        let spec: Spec = resp.error_for_status()?.json().await?;
+                        spec.source.git.into_iter().chain(spec.homepage).collect()
+                    };
+                    ctx.cache_put(&log, &cache_key, &candidates).await;
+                    candidates
+                },
+            };
+            for c in candidates {
+                if ctx.maybe_add_url(&log, &c).await {
+                    return Ok(());
+                }
+            }
+            warn!(log, "No repo-ish url found in dep metadata");
+            Ok(())
+        }).await {
+            Ok(_) => { },
+            Err(e) => {
+                warn!(
+                    log,
+                    "Error processing dependency";
+                    "err" => #? e
+                );
+            },
+        }
+    }));
+}
+
+fn pod_name(entry: &serde_yaml::Value) -> Option<String> {
+    let entry = match entry {
+        serde_yaml::Value::Mapping(m) => m.keys().next()?,
+        e => e,
+    };
+    let entry = entry.as_str()?;
+    Some(entry.split([' ', '/']).next()?.to_string())
+}
+
+pub fn process_swift_cocoapods(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let lock_path = path.join("Podfile.lock");
+    let log = log.new(o!("file" => lock_path.to_string_lossy().to_string()));
+    let lock: serde_yaml::Value = match maybe_read(&lock_path).and_then(|b| match b {
+        Some(b) => Ok(Some(serde_yaml::from_slice(&b)?)),
+        None => Ok(None),
+    }) {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading Podfile.lock";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+    let entries = |key: &str| -> BTreeSet<String> {
+        lock.get(key).and_then(|v| v.as_sequence()).into_iter().flatten().filter_map(pod_name).collect()
+    };
+    let pods = if ctx.supercontext.include_indirect {
+        entries("PODS")
+    } else {
+        entries("DEPENDENCIES")
+    };
+    for name in pods {
+        match lock.get("EXTERNAL SOURCES").and_then(|s| s.get(name.as_str())) {
+            Some(external) => {
+                if let Some(git) = external.get(":git").and_then(|g| g.as_str()) {
+                    process_git_dep(&log, ctx, pool, git.to_string());
+                }
+
+                // Local paths and podspec urls aren't projects
+            },
+            None => process_pod(&log, ctx, pool, name),
+        }
+    }
+}
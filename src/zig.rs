@@ -0,0 +1,85 @@
+use std::path::Path;
+use slog::{
+    Logger,
+    o,
+    warn,
+};
+use structre::structre;
+use tokio::{
+    spawn,
+    task::JoinHandle,
+};
+use crate::common::{
+    Context,
+    DepRole,
+    maybe_read,
+};
+
+#[structre(r#"\.url\s*=\s*"(?P<url>[^"]+)""#)]
+struct ZonUrl {
+    url: String,
+}
+
+fn repo_root(url: &str) -> Option<String> {
+    if let Some(git) = url.strip_prefix("git+") {
+        return Some(git.split(['#', '?']).next().unwrap().trim_end_matches(".git").to_string());
+    }
+    let (root, _) = url.split_once("/archive/")?;
+    Some(root.to_string())
+}
+
+pub fn process_zig(log: &Logger, ctx: &Context, pool: &mut Vec<JoinHandle<()>>, path: &Path) {
+    let zon_path = path.join("build.zig.zon");
+    let log = log.new(o!("file" => zon_path.to_string_lossy().to_string()));
+    let text = match maybe_read(&zon_path) {
+        Ok(Some(b)) => String::from_utf8_lossy(&b).to_string(),
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                log,
+                "Error loading build.zig.zon";
+                "err" => #? e
+            );
+            return;
+        },
+    };
+
+    // Only dependencies have urls; local dependencies have a `.path` instead
+    let ctx = ctx.with_role(DepRole::Runtime);
+    let parse = ZonUrlFromRegex::new();
+    for line in text.lines() {
+        let url = match parse.parse(line) {
+            Ok(u) => u.url,
+            Err(_) => continue,
+        };
+        let log = log.new(o!("dep" => url.clone()));
+        let ctx = ctx.clone();
+        pool.push(spawn(async move {
+            if ctx.maybe_add_url(&log, url.strip_prefix("git+").unwrap_or(&url)).await {
+                return;
+            }
+            match repo_root(&url) {
+                Some(root) => ctx.add_url(&root).await,
+                None => warn!(log, "Unable to determine repo for dependency url"),
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repo_roots() {
+        assert_eq!(
+            repo_root("git+https://codeberg.org/a/b.git#0123abc").as_deref(),
+            Some("https://codeberg.org/a/b"),
+        );
+        assert_eq!(
+            repo_root("https://github.com/a/b/archive/refs/tags/v1.0.tar.gz").as_deref(),
+            Some("https://github.com/a/b"),
+        );
+        assert_eq!(repo_root("https://example.com/b-1.0.tar.gz"), None);
+    }
+}